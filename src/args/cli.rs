@@ -1,25 +1,226 @@
 use std::path::PathBuf;
 
-use clap::Parser;
+use clap::{Parser, Subcommand};
+use clap::builder::RangedU64ValueParser;
+
+use crate::handbrake::{DisposeAfter, OnExisting, ReportFormat, ScanFormat, SourceAction};
 
 /// Automating handbrake to work with mkv-renamer.
 ///
-/// Note: Your handbrake profiles should be created under ~/.deo/profiles after exporting from the handbrake UI.
+/// Note: Your handbrake profiles should be created under ~/.deo/profiles, or the profiles_dir in ~/.deo/config.toml, after exporting from the handbrake UI.
 #[derive(Parser, Debug, Clone)]
 #[command(author, version, about)]
 pub struct Args {
 
+   #[command(subcommand)]
+   pub command: Option<DeoCommand>,
+
    /// Source directory that contains Rips/sessionX/renames and Encodes
-   #[arg(short, long)]
-   pub source: PathBuf,
+   ///
+   /// Defaults to the source in the config file.
+   #[arg(short, long, global = true)]
+   pub source: Option<PathBuf>,
+
+   /// Config file to use instead of ~/.deo/config.toml
+   #[arg(long, env = "DEO_CONFIG", global = true)]
+   pub config: Option<PathBuf>,
 
    /// Verbose debug logging
    ///
    /// You can get very detailed logging of what deo is considering when using verbose logging
-   #[arg(long)]
-   pub verbose: bool
+   #[arg(long, global = true)]
+   pub verbose: bool,
+
+   /// Profile to encode every selected session with, instead of prompting for one
+   ///
+   /// The profile name is the file name of the profile in the profiles directory without the .json extension.
+   #[arg(long, global = true)]
+   pub profile: Option<String>,
+
+   /// Session to encode (eg. session1). Can be supplied multiple times
+   ///
+   /// Only the supplied sessions will be considered for encoding. When omitted, all sessions are considered.
+   #[arg(long = "session", value_name = "SESSION", global = true)]
+   pub sessions: Vec<String>,
+
+   /// Proceed with encoding without asking for confirmation
+   ///
+   /// Only the final confirmation, and the one before replacing the journal of an unfinished run, are skipped. Without --profile, deo still prompts for the files and profile of each session, so combine it with --profile to run deo without any prompts, such as from cron.
+   #[arg(short, long, global = true)]
+   pub yes: bool,
+
+   /// Print the encoder commands that would be run, without encoding anything
+   ///
+   /// Sessions are scanned, profiles are loaded and selections are made as usual. The full command line and output path of every file is then printed instead of being encoded. The encoding log is not touched.
+   #[arg(long, global = true)]
+   pub dry_run: bool,
+
+   /// Extension to give encoded files (eg. m4v), instead of the one for the container of their profile
+   ///
+   /// Outputs are named after their rename with the extension of the container the profile encodes to: mp4 unless the FileFormat of a handbrake profile or the Extension of an ffmpeg profile says otherwise. deo warns when a profile's container doesn't match this extension. Defaults to the extension in the config file.
+   #[arg(long, value_name = "EXT", global = true)]
+   pub extension: Option<String>,
+
+   /// What to do when an encoded file already exists in the Encodes directory
+   #[arg(long, value_enum, default_value_t = OnExisting::Overwrite, global = true)]
+   pub on_existing: OnExisting,
+
+   /// Number of files to encode at the same time
+   ///
+   /// Each running encode gets its own progress bar. Higher values suit machines with many cores and light profiles. Defaults to the jobs in the config file, or 1.
+   #[arg(short, long, value_parser = RangedU64ValueParser::<usize>::new().range(1..), global = true)]
+   pub jobs: Option<usize>,
+
+   /// Keep the raw JSON output of handbrake for each file
+   ///
+   /// The stderr output of handbrake is always saved to a log file per encoded file, under the directory of the run in the log directory. This also saves the JSON handbrake writes to stdout next to it.
+   #[arg(long, global = true)]
+   pub keep_json_output: bool,
+
+   /// Verify each file after encoding it
+   ///
+   /// The output must exist, must not be empty and must be as long as its source, within --verify-tolerance. Durations are found by scanning both files with handbrakecli. Files that fail verification are counted and logged separately from encoding failures. Defaults to verify in the config file.
+   #[arg(long, overrides_with = "no_verify", global = true)]
+   pub verify: bool,
+
+   /// Don't verify files after encoding them, even when verify is set in the config file
+   #[arg(long, overrides_with = "verify", global = true)]
+   pub no_verify: bool,
+
+   /// Number of seconds the duration of an output may differ from its source when verifying
+   ///
+   /// Defaults to the verify_tolerance in the config file, or 2.
+   #[arg(long, value_name = "SECONDS", global = true)]
+   pub verify_tolerance: Option<u64>,
+
+   /// Fail a file when handbrake makes no progress for this many minutes
+   ///
+   /// Handbrake can hang on a damaged source. The stalled handbrake is killed, its partial output is deleted and the next file is encoded. Defaults to the stall_timeout in the config file, or no timeout.
+   #[arg(long, value_name = "MINUTES", value_parser = RangedU64ValueParser::<u64>::new().range(1..), global = true)]
+   pub stall_timeout: Option<u64>,
+
+   /// Fail a file when encoding it takes longer than this many minutes
+   ///
+   /// Handbrake is killed, its partial output is deleted and the next file is encoded. Defaults to the max_file_time in the config file, or no limit.
+   #[arg(long, value_name = "MINUTES", value_parser = RangedU64ValueParser::<u64>::new().range(1..), global = true)]
+   pub max_file_time: Option<u64>,
+
+   /// Number of times to retry a file that fails to encode or fails verification
+   ///
+   /// When the profile of a file has a fallback profile in the config file, the last attempt uses the fallback profile. Every attempt is recorded in the run log. Defaults to the retries in the config file, or 0.
+   #[arg(long, value_name = "N", global = true)]
+   pub retries: Option<usize>,
+
+   /// What to do with the source of a file once it has been encoded
+   ///
+   /// Sources that failed to encode, failed verification or were cancelled are never deleted or archived. Defaults to the dispose_source in the config file, or keep.
+   #[arg(long, value_enum, global = true)]
+   pub dispose_source: Option<SourceAction>,
+
+   /// When to dispose of the sources of encoded files
+   ///
+   /// Defaults to the dispose_after in the config file, or file.
+   #[arg(long, value_enum, global = true)]
+   pub dispose_after: Option<DisposeAfter>,
+
+   /// Directory that sources are moved to by --dispose-source archive, in a directory per session
+   ///
+   /// Defaults to the archive_dir in the config file.
+   #[arg(long, value_name = "DIR", global = true)]
+   pub archive_dir: Option<PathBuf>,
+
+   /// Directory that the sources of files that failed to encode are moved to, along with their logs
+   ///
+   /// Sources are moved into a directory per session. Sources that failed verification are never moved. Defaults to the failed_dir in the config file, or leaving failed sources where they are.
+   #[arg(long, value_name = "DIR", global = true)]
+   pub failed_dir: Option<PathBuf>,
+
+   /// Directory to encode files in before moving them to the Encodes directory
+   ///
+   /// Files are always encoded to a hidden partial file, and only moved to their name in the Encodes directory once they have been encoded and verified. The partial files are kept next to the output unless this is supplied. Defaults to the scratch_dir in the config file.
+   #[arg(long, value_name = "DIR", global = true)]
+   pub scratch_dir: Option<PathBuf>,
+
+   /// Gibibytes of free space to keep on the filesystems that files are encoded to
+   ///
+   /// Before encoding, the size of each output is estimated from its source, using how well its profile has compressed in earlier runs. Encoding doesn't start unless every filesystem has room for the outputs and this much to spare. During the run, no more files are started once a filesystem has less than this free. Defaults to the min_free_space in the config file, or 1.
+   #[arg(long, value_name = "GIB", global = true)]
+   pub min_free_space: Option<u64>,
+
+   /// Start encoding even when the outputs are estimated not to fit
+   ///
+   /// Files are still not started once a filesystem has less than --min-free-space free.
+   #[arg(long, global = true)]
+   pub no_space_check: bool,
+
+   /// Write the summary of the run to this file, for your encode notes
+   ///
+   /// The summary of every run is printed when it finishes, with the status, time, sizes and compression ratio of each file by session. The report is written as Markdown, or as JSON when the file has a .json extension.
+   #[arg(long, value_name = "PATH", global = true)]
+   pub report: Option<PathBuf>,
+
+   /// Format of the --report file, instead of choosing it by the extension of the file
+   #[arg(long, value_enum, requires = "report", global = true)]
+   pub report_format: Option<ReportFormat>,
+}
+
+impl Args {
+  /// Whether --verify or --no-verify was supplied, with the last one winning.
+  pub fn verify(&self) -> Option<bool> {
+    match (self.verify, self.no_verify) {
+      (true, _) => Some(true),
+      (_, true) => Some(false),
+      _ => None,
+    }
+  }
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum DeoCommand {
+  /// Resume the last run from its first unfinished file, without any prompts
+  ///
+  /// Every run saves its queue to ~/.deo/journal.json before encoding starts.
+  Resume,
+
+  /// Scan the renames of every session with handbrake, and list what they contain
+  ///
+  /// Prints the duration, resolution, video codec, HDR formats, and the audio and subtitle tracks with their languages of every file that would be offered for encoding. Use it to spot mislabelled rips and to choose profiles before encoding. Only the sessions supplied with --session are scanned, when any are.
+  Scan {
+    /// Print a table per session, or JSON for other tools
+    #[arg(long, value_enum, default_value_t = ScanFormat::Table)]
+    format: ScanFormat,
+  },
+
+  /// Keep running, and encode each session as soon as mkv-renamer has finished it
+  ///
+  /// The renames are scanned when deo starts, again whenever an encode_dir.txt or a rename changes, and every --interval seconds. New files are queued with the profile of the rule that matches their session, or else with --profile or the default_profile in the config file, and encoded without any prompts. Files that have been queued since deo started, or that an earlier run has encoded, are never queued again. Press Ctrl-C to stop watching.
+  Watch {
+    /// Seconds between scans, which are the only scans when the filesystem can't notify deo of changes, such as on some network shares
+    #[arg(long, value_name = "SECONDS", default_value_t = 60, value_parser = RangedU64ValueParser::<u64>::new().range(1..))]
+    interval: u64,
+  },
 }
 
 pub fn get_cli_args() -> Args {
   Args::parse()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn verify(args: &[&str]) -> Option<bool> {
+      Args::parse_from(std::iter::once("deo").chain(args.iter().copied())).verify()
+    }
+
+    #[test]
+    fn verifies_with_the_last_verify_flag_supplied() {
+      assert_eq!(verify(&[]), None);
+      assert_eq!(verify(&["--verify"]), Some(true));
+      assert_eq!(verify(&["--no-verify"]), Some(false));
+      assert_eq!(verify(&["--verify", "--no-verify"]), Some(false));
+      assert_eq!(verify(&["--no-verify", "--verify"]), Some(true));
+      assert_eq!(verify(&["resume", "--no-verify"]), Some(false));
+    }
+}
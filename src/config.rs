@@ -0,0 +1,403 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use serde::Deserialize;
+use dirs::home_dir;
+
+use crate::error::{DeoConfigError, FileName};
+use crate::handbrake::{DisposeAfter, Encoders, FfmpegCli, HandbrakeCli, Hooks, SourceAction};
+
+/// Settings read from ~/.deo/config.toml. Every setting is optional, and any matching command line argument takes
+/// precedence over it.
+///
+/// Paths may start with `~/` to refer to the home directory.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DeoConfig {
+  #[serde(skip)]
+  deo_dir: PathBuf,
+
+  /// Path or name of the handbrake executable
+  handbrake: Option<String>,
+
+  /// Path or name of the ffmpeg executable, for ffmpeg profiles
+  ffmpeg: Option<String>,
+
+  /// Path or name of the ffprobe executable, used to find the duration of files for ffmpeg profiles
+  ffprobe: Option<String>,
+
+  /// Arguments passed to every invocation of handbrake
+  #[serde(default)]
+  handbrake_args: Vec<String>,
+
+  /// Directory of exported handbrake profiles
+  profiles_dir: Option<String>,
+
+  /// Source directory to use when --source is not supplied
+  source: Option<String>,
+
+  /// Directory that holds the logs of every run
+  log_dir: Option<String>,
+
+  /// Directory that files are encoded to before being moved to Encodes when --scratch-dir is not supplied
+  scratch_dir: Option<String>,
+
+  /// File of rules that choose the profile of each session
+  rules_file: Option<String>,
+
+  /// Profile to queue new sessions with in watch mode when --profile is not supplied
+  default_profile: Option<String>,
+
+  /// Extension to give encoded files when --extension is not supplied
+  extension: Option<String>,
+
+  /// Number of files to encode at the same time when --jobs is not supplied
+  jobs: Option<usize>,
+
+  /// Whether to verify each file after encoding it when --verify is not supplied
+  verify: Option<bool>,
+
+  /// Number of seconds the duration of an output may differ from its source when --verify-tolerance is not supplied
+  verify_tolerance: Option<u64>,
+
+  /// Minutes handbrake may go without making progress when --stall-timeout is not supplied
+  stall_timeout: Option<u64>,
+
+  /// Minutes handbrake may spend on a single file when --max-file-time is not supplied
+  max_file_time: Option<u64>,
+
+  /// Number of times to retry a failed file when --retries is not supplied
+  retries: Option<usize>,
+
+  /// Profile to use for the last attempt at a file, by the name of the profile it was selected with
+  #[serde(default)]
+  fallbacks: BTreeMap<String, String>,
+
+  /// What to do with the source of a file once it has been encoded when --dispose-source is not supplied
+  dispose_source: Option<SourceAction>,
+
+  /// When to dispose of the sources of encoded files when --dispose-after is not supplied
+  dispose_after: Option<DisposeAfter>,
+
+  /// Directory that sources are archived to when --archive-dir is not supplied
+  archive_dir: Option<String>,
+
+  /// Directory that the sources of failed files are moved to when --failed-dir is not supplied
+  failed_dir: Option<String>,
+
+  /// Gibibytes to keep free on the filesystems that files are encoded to when --min-free-space is not supplied
+  min_free_space: Option<u64>,
+
+  /// Ratio of output to source size to expect from profiles that haven't encoded anything yet
+  output_size_factor: Option<f64>,
+
+  /// Commands to run around the run and around each file
+  #[serde(default)]
+  hooks: Hooks,
+}
+
+impl DeoConfig {
+  pub fn handbrake_cli(&self) -> HandbrakeCli {
+    let default_cli = HandbrakeCli::default();
+
+    HandbrakeCli {
+      executable: self.handbrake.clone().map_or(default_cli.executable, |h| expand_home(&h).to_string_lossy().to_string()),
+      global_args: self.handbrake_args.clone(),
+    }
+  }
+
+  pub fn ffmpeg_cli(&self) -> FfmpegCli {
+    let default_cli = FfmpegCli::default();
+    let executable = |setting: &Option<String>, default: String| setting.clone().map_or(default, |e| expand_home(&e).to_string_lossy().to_string());
+
+    FfmpegCli {
+      executable: executable(&self.ffmpeg, default_cli.executable),
+      ffprobe: executable(&self.ffprobe, default_cli.ffprobe),
+    }
+  }
+
+  pub fn encoders(&self) -> Encoders {
+    Encoders {
+      handbrake: self.handbrake_cli(),
+      ffmpeg: self.ffmpeg_cli(),
+    }
+  }
+
+  pub fn profiles_dir(&self) -> PathBuf {
+    self
+      .profiles_dir
+      .as_deref()
+      .map_or_else(|| self.deo_dir.join("profiles"), expand_home)
+  }
+
+  pub fn log_dir(&self) -> PathBuf {
+    self
+      .log_dir
+      .as_deref()
+      .map_or_else(|| self.deo_dir.join("logs"), expand_home)
+  }
+
+  pub fn scratch_dir(&self) -> Option<PathBuf> {
+    self
+      .scratch_dir
+      .as_deref()
+      .map(expand_home)
+  }
+
+  pub fn source(&self) -> Option<PathBuf> {
+    self
+      .source
+      .as_deref()
+      .map(expand_home)
+  }
+
+  pub fn jobs(&self) -> Option<usize> {
+    self.jobs
+  }
+
+  pub fn verify(&self) -> Option<bool> {
+    self.verify
+  }
+
+  pub fn verify_tolerance(&self) -> Option<u64> {
+    self.verify_tolerance
+  }
+
+  pub fn stall_timeout(&self) -> Option<u64> {
+    self.stall_timeout
+  }
+
+  pub fn max_file_time(&self) -> Option<u64> {
+    self.max_file_time
+  }
+
+  pub fn retries(&self) -> Option<usize> {
+    self.retries
+  }
+
+  pub fn fallback_profile(&self, profile: &str) -> Option<&str> {
+    self
+      .fallbacks
+      .get(profile)
+      .map(String::as_str)
+  }
+
+  pub fn dispose_source(&self) -> Option<SourceAction> {
+    self.dispose_source
+  }
+
+  pub fn dispose_after(&self) -> Option<DisposeAfter> {
+    self.dispose_after
+  }
+
+  pub fn archive_dir(&self) -> Option<PathBuf> {
+    self
+      .archive_dir
+      .as_deref()
+      .map(expand_home)
+  }
+
+  pub fn failed_dir(&self) -> Option<PathBuf> {
+    self
+      .failed_dir
+      .as_deref()
+      .map(expand_home)
+  }
+
+  /// The rules file and whether it must exist, which it must when it has been set in the config file.
+  pub fn rules_file(&self) -> (PathBuf, bool) {
+    match self.rules_file.as_deref() {
+      Some(rules_file) => (expand_home(rules_file), true),
+      None => (self.deo_dir.join("rules.toml"), false),
+    }
+  }
+
+  pub fn default_profile(&self) -> Option<String> {
+    self.default_profile.clone()
+  }
+
+  pub fn extension(&self) -> Option<String> {
+    self.extension.clone()
+  }
+
+  pub fn min_free_space(&self) -> Option<u64> {
+    self.min_free_space
+  }
+
+  pub fn output_size_factor(&self) -> f64 {
+    self.output_size_factor.unwrap_or(1.0)
+  }
+
+  pub fn hooks(&self) -> Hooks {
+    self.hooks.clone()
+  }
+
+  /// Settings that decode but can't be used.
+  fn validate(&self) -> Result<(), String> {
+    let zero_settings: Vec<&str> =
+      [("jobs", self.jobs.map(|jobs| jobs as u64)), ("stall_timeout", self.stall_timeout), ("max_file_time", self.max_file_time)]
+        .into_iter()
+        .filter_map(|(name, value)| (value == Some(0)).then_some(name))
+        .collect();
+
+    if zero_settings.is_empty() {
+      Ok(())
+    } else {
+      Err(format!("{} must be at least 1", zero_settings.join(", ")))
+    }
+  }
+}
+
+/// Read the config file. An explicitly supplied config file must exist, while the default config file is optional.
+pub fn read_config(config_file: Option<&Path>) -> Result<DeoConfig, DeoConfigError> {
+  let deo_dir =
+    home_dir()
+      .ok_or(DeoConfigError::CouldNotFindHomeDir)?
+      .join(".deo");
+
+  let config_path =
+    match config_file {
+      Some(config_file) if !config_file.exists() => return Err(DeoConfigError::ConfigFileDoesNotExist(FileName::new(config_file))),
+      Some(config_file) => config_file.to_owned(),
+      None => deo_dir.join("config.toml"),
+    };
+
+  if !config_path.exists() {
+    Ok(DeoConfig { deo_dir, ..DeoConfig::default() })
+  } else {
+    std::fs::read_to_string(&config_path)
+      .map_err(|e| DeoConfigError::CouldNotReadConfig(FileName::new(&config_path), e.to_string()))
+      .and_then(|config_toml| {
+        toml::from_str::<DeoConfig>(&config_toml)
+          .map_err(|e| DeoConfigError::CouldNotDecodeConfig(FileName::new(&config_path), e.to_string()))
+      })
+      .and_then(|config| {
+        config
+          .validate()
+          .map_err(|e| DeoConfigError::CouldNotDecodeConfig(FileName::new(&config_path), e))
+          .map(|_| DeoConfig { deo_dir, ..config })
+      })
+  }
+}
+
+fn expand_home(path: &str) -> PathBuf {
+  match (path.strip_prefix("~/"), home_dir()) {
+    (Some(relative_path), Some(home)) => home.join(relative_path),
+    _ => PathBuf::from(path),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn decodes_all_settings() {
+      let config_toml = r#"
+        handbrake = "/usr/local/bin/HandBrakeCLI"
+        handbrake_args = ["--verbose", "0"]
+        ffmpeg = "/opt/ffmpeg/bin/ffmpeg"
+        ffprobe = "/opt/ffmpeg/bin/ffprobe"
+        profiles_dir = "/media/profiles"
+        source = "/media/processing"
+        log_dir = "/var/log/deo"
+        scratch_dir = "/scratch/deo"
+        rules_file = "/media/rules.toml"
+        default_profile = "Fast 1080p"
+        extension = "m4v"
+        jobs = 4
+        verify = true
+        verify_tolerance = 5
+        stall_timeout = 10
+        max_file_time = 180
+        retries = 2
+        dispose_source = "archive"
+        dispose_after = "session"
+        archive_dir = "/media/archive"
+        failed_dir = "/media/failed"
+        min_free_space = 20
+        output_size_factor = 0.4
+
+        [fallbacks]
+        "Fast 1080p" = "Fast 1080p Software"
+
+        [hooks]
+        file_pre = "test -w \"$DEO_OUTPUT\""
+        run_end = "notify-send deo done"
+      "#;
+
+      let config: DeoConfig = toml::from_str(config_toml).unwrap();
+      let handbrake_cli = config.handbrake_cli();
+
+      assert_eq!(handbrake_cli.executable, "/usr/local/bin/HandBrakeCLI");
+      assert_eq!(handbrake_cli.global_args, vec!["--verbose".to_owned(), "0".to_owned()]);
+      assert_eq!(config.ffmpeg_cli().executable, "/opt/ffmpeg/bin/ffmpeg");
+      assert_eq!(config.ffmpeg_cli().ffprobe, "/opt/ffmpeg/bin/ffprobe");
+      assert_eq!(config.profiles_dir(), PathBuf::from("/media/profiles"));
+      assert_eq!(config.source(), Some(PathBuf::from("/media/processing")));
+      assert_eq!(config.log_dir(), PathBuf::from("/var/log/deo"));
+      assert_eq!(config.scratch_dir(), Some(PathBuf::from("/scratch/deo")));
+      assert_eq!(config.rules_file(), (PathBuf::from("/media/rules.toml"), true));
+      assert_eq!(config.default_profile(), Some("Fast 1080p".to_owned()));
+      assert_eq!(config.extension(), Some("m4v".to_owned()));
+      assert_eq!(config.jobs(), Some(4));
+      assert_eq!(config.verify(), Some(true));
+      assert_eq!(config.verify_tolerance(), Some(5));
+      assert_eq!(config.stall_timeout(), Some(10));
+      assert_eq!(config.max_file_time(), Some(180));
+      assert_eq!(config.retries(), Some(2));
+      assert_eq!(config.fallback_profile("Fast 1080p"), Some("Fast 1080p Software"));
+      assert_eq!(config.fallback_profile("HQ 1080p"), None);
+      assert_eq!(config.dispose_source(), Some(SourceAction::Archive));
+      assert_eq!(config.dispose_after(), Some(DisposeAfter::Session));
+      assert_eq!(config.archive_dir(), Some(PathBuf::from("/media/archive")));
+      assert_eq!(config.failed_dir(), Some(PathBuf::from("/media/failed")));
+      assert_eq!(config.min_free_space(), Some(20));
+      assert_eq!(config.output_size_factor(), 0.4);
+      assert_eq!(config.hooks().file_pre, Some("test -w \"$DEO_OUTPUT\"".to_owned()));
+      assert_eq!(config.hooks().run_end, Some("notify-send deo done".to_owned()));
+      assert_eq!(config.hooks().run_start, None);
+    }
+
+    #[test]
+    fn defaults_missing_settings() {
+      let config = DeoConfig { deo_dir: PathBuf::from("/home/someone/.deo"), ..toml::from_str("").unwrap() };
+
+      assert_eq!(config.handbrake_cli().executable, "handbrakecli");
+      assert_eq!(config.ffmpeg_cli().executable, "ffmpeg");
+      assert_eq!(config.ffmpeg_cli().ffprobe, "ffprobe");
+      assert_eq!(config.profiles_dir(), PathBuf::from("/home/someone/.deo/profiles"));
+      assert_eq!(config.log_dir(), PathBuf::from("/home/someone/.deo/logs"));
+      assert_eq!(config.scratch_dir(), None);
+      assert_eq!(config.rules_file(), (PathBuf::from("/home/someone/.deo/rules.toml"), false));
+      assert_eq!(config.default_profile(), None);
+      assert_eq!(config.extension(), None);
+      assert_eq!(config.source(), None);
+      assert_eq!(config.jobs(), None);
+      assert_eq!(config.verify(), None);
+      assert_eq!(config.verify_tolerance(), None);
+      assert_eq!(config.stall_timeout(), None);
+      assert_eq!(config.max_file_time(), None);
+      assert_eq!(config.retries(), None);
+      assert_eq!(config.fallback_profile("Fast 1080p"), None);
+      assert_eq!(config.dispose_source(), None);
+      assert_eq!(config.dispose_after(), None);
+      assert_eq!(config.archive_dir(), None);
+      assert_eq!(config.failed_dir(), None);
+      assert_eq!(config.min_free_space(), None);
+      assert_eq!(config.output_size_factor(), 1.0);
+      assert_eq!(config.hooks().file_post, None);
+    }
+
+    #[test]
+    fn rejects_zero_jobs_and_timeouts() {
+      let config: DeoConfig = toml::from_str("jobs = 0\nstall_timeout = 0\nmax_file_time = 60").unwrap();
+
+      assert_eq!(config.validate(), Err("jobs, stall_timeout must be at least 1".to_owned()))
+    }
+
+    #[test]
+    fn rejects_unknown_settings() {
+      assert!(toml::from_str::<DeoConfig>("handbreak = \"HandBrakeCLI\"").is_err())
+    }
+}
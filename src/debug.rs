@@ -11,6 +11,7 @@ use crate::colours::*;
 use crate::models::TVSeriesEncodeDir;
 use crate::models::TVSeriesSession;
 use crate::models::TVSeriesToEncodeDir;
+use crate::rules::{RuleMatch, SessionDetails};
 
 // See: https://askubuntu.com/questions/821157/print-a-256-color-test-pattern-in-the-terminal
 pub fn dump_entry_types(entry_types: &[EntryType], verbose: bool) {
@@ -80,9 +81,8 @@ pub fn dump_tv_sessions_hash(session_hash: &HashMap<SessionId, TVSeriesSession>,
         let pathbuf = file.path;
         let path = pathbuf.to_string_lossy();
         let mkv_file = file.mkv_file;
-        let mp4_file = file.mp4_file;
         let session_id = file.session;
-        let msg = style(format!("\n  tvseries session:{session_id}\n  path:{path}\n  episode:{episode}\n  mkv_file:{mkv_file}\n  mp4_file:{mp4_file}")).bg(GRAY);
+        let msg = style(format!("\n  tvseries session:{session_id}\n  path:{path}\n  episode:{episode}\n  mkv_file:{mkv_file}")).bg(GRAY);
         println!("{}", msg);
         println!();
       }
@@ -100,9 +100,8 @@ pub fn dump_movie_sessions_hash(session_hash: &HashMap<SessionId, MovieSession>,
         let pathbuf = file.path;
         let path = pathbuf.to_string_lossy();
         let mkv_file = file.mkv_file;
-        let mp4_file = file.mp4_file;
         let session_id = file.session;
-        let msg = style(format!("\n  movie session:{session_id}\n  path:{path}\n  mkv_file:{mkv_file}\n  mp4_file:{mp4_file}")).bg(GRAY);
+        let msg = style(format!("\n  movie session:{session_id}\n  path:{path}\n  mkv_file:{mkv_file}")).bg(GRAY);
         println!("{}", msg);
         println!();
       }
@@ -169,10 +168,9 @@ fn dump_tv_series_to_encode_dirs(sted: &TVSeriesToEncodeDir) {
     let path = pathbuf.to_string_lossy();
     let episode = file.episode;
     let mkv_file = file.mkv_file;
-    let mp4_file = file.mp4_file;
     let session_session_id = sted.session_id();
 
-    let session_msg = style(format!("\n  TV Series Session:\n    session:{session_session_id}\n    path:{path}\n{episode}    mkv_file:{mkv_file}\n    mp4_file:{mp4_file}")).bg(GRAY);
+    let session_msg = style(format!("\n  TV Series Session:\n    session:{session_session_id}\n    path:{path}\n{episode}    mkv_file:{mkv_file}")).bg(GRAY);
     println!("{}", session_msg);
     println!();
   }
@@ -194,10 +192,9 @@ fn dump_movie_to_encode_dirs(sted: &MovieToEncodeDir) {
     let pathbuf = file.path;
     let path = pathbuf.to_string_lossy();
     let mkv_file = file.mkv_file;
-    let mp4_file = file.mp4_file;
     let session_session_id = sted.session_id();
 
-    let session_msg = style(format!("\n  Movie Session:\n    session:{session_session_id}\n    path:{path}\n    mkv_file:{mkv_file}\n    mp4_file:{mp4_file}")).bg(GRAY);
+    let session_msg = style(format!("\n  Movie Session:\n    session:{session_session_id}\n    path:{path}\n    mkv_file:{mkv_file}")).bg(GRAY);
     println!("{}", session_msg);
     println!();
   }
@@ -225,10 +222,9 @@ pub fn dump_unmapped_tv_series_sessions_and_encode_dirs(tv_series_session_to_enc
             let path = pathbuf.to_string_lossy();
             let episode = &file.episode;
             let mkv_file = file.mkv_file;
-            let mp4_file = file.mp4_file;
             let session_session_id = file.session;
 
-            let session_msg = style(format!("\n  Session:\n    session:{session_session_id}\n    path:{path}\n    episode:{episode}\n    mkv_file:{mkv_file}\n    mp4_file:{mp4_file}")).bg(GRAY);
+            let session_msg = style(format!("\n  Session:\n    session:{session_session_id}\n    path:{path}\n    episode:{episode}\n    mkv_file:{mkv_file}")).bg(GRAY);
             println!("{}", session_msg);
             println!();
           }
@@ -276,10 +272,9 @@ pub(crate) fn dump_unmapped_movie_sessions_and_encode_dirs(movie_session_to_enco
             let pathbuf = file.path;
             let path = pathbuf.to_string_lossy();
             let mkv_file = file.mkv_file;
-            let mp4_file = file.mp4_file;
             let session_session_id = file.session;
 
-            let session_msg = style(format!("\n  Session:\n    session:{session_session_id}\n    path:{path}\n    mkv_file:{mkv_file}\n    mp4_file:{mp4_file}")).bg(GRAY);
+            let session_msg = style(format!("\n  Session:\n    session:{session_session_id}\n    path:{path}\n    mkv_file:{mkv_file}")).bg(GRAY);
             println!("{}", session_msg);
             println!();
           }
@@ -318,8 +313,7 @@ pub fn dump_mixed_sessions(tv_series_session: &HashMap<SessionId, TVSeriesSessio
             let path = &tv_series_file.path.to_string_lossy();
             let episode = tv_series_file.episode;
             let mkv_file = tv_series_file.mkv_file;
-            let mp4_file = tv_series_file.mp4_file;
-            let tv_session_msg = style(format!("\n  TV Series Session:\n    session:{session_id}\n    path:{path}\n{episode}    mkv_file:{mkv_file}\n    mp4_file:{mp4_file}")).bg(GRAY);
+            let tv_session_msg = style(format!("\n  TV Series Session:\n    session:{session_id}\n    path:{path}\n{episode}    mkv_file:{mkv_file}")).bg(GRAY);
             println!("{}", tv_session_msg);
             println!();
           }
@@ -327,9 +321,8 @@ pub fn dump_mixed_sessions(tv_series_session: &HashMap<SessionId, TVSeriesSessio
           for movie_file in movie_session.files() {
             let path = &movie_file.path.to_string_lossy();
             let mkv_file = movie_file.mkv_file;
-            let mp4_file = movie_file.mp4_file;
 
-            let movie_session_msg = style(format!("\n  Movie Session:\n    session:{session_id}\n    path:{path}\n    mkv_file:{mkv_file}\n    mp4_file:{mp4_file}")).bg(GRAY);
+            let movie_session_msg = style(format!("\n  Movie Session:\n    session:{session_id}\n    path:{path}\n    mkv_file:{mkv_file}")).bg(GRAY);
             println!("{}", movie_session_msg);
             println!();
           }
@@ -337,3 +330,19 @@ pub fn dump_mixed_sessions(tv_series_session: &HashMap<SessionId, TVSeriesSessio
     }
   }
 }
+
+pub fn dump_rule_match(details: &SessionDetails, rule_match: &RuleMatch, verbose: bool) {
+  if verbose {
+    let tvdb_id = details.tvdb_id.as_deref().unwrap_or("-");
+    let matched =
+      match rule_match {
+        RuleMatch::NoMatch => "no rule".to_owned(),
+        RuleMatch::Matched(rule) => format!("rule: {}", rule),
+        RuleMatch::Conflict(rules) => format!("conflicting rules: {}", rules.iter().map(|rule| rule.name()).collect::<Vec<_>>().join(", ")),
+      };
+
+    let msg = style(format!("Rules:\n  session:{}\n  type:{}\n  title:{}\n  tvdb:{}\n  matched {}", details.session_id, details.content_type, details.title, tvdb_id, matched)).bg(GRAY);
+    println!("{}", msg);
+    println!()
+  }
+}
@@ -5,8 +5,11 @@ pub enum DeoProfileError {
   CouldNotReadProfile(FileName, String),
   CouldNotDecodeProfile(FileName, String),
   ProfilePresetNameIsNotString(FileName, String),
+  ProfileArgumentsAreNotStrings(FileName, String),
+  UnknownProfileBackend(FileName, String),
+  UnknownProfileFileFormat(FileName, String),
+  UnknownProfileExtension(FileName, String),
   ProfilesDirDoesNotExist(DirName),
-  CouldNotFindHomeDir,
   NoProfilesFound(DirName),
 }
 
@@ -16,8 +19,11 @@ impl fmt::Display for DeoProfileError {
         DeoProfileError::CouldNotReadProfile(profile, error) => format!("Could not read profile file: {profile}, due to: {error}"),
         DeoProfileError::CouldNotDecodeProfile(profile, error) => format!("Could not decode profile file: {profile}, due to: {error}"),
         DeoProfileError::ProfilePresetNameIsNotString(profile, error) => format!("Profile: {profile} has an invalid preset value: {error}"),
+        DeoProfileError::ProfileArgumentsAreNotStrings(profile, error) => format!("Profile: {profile} needs Arguments that are a list of strings, but has: {error}"),
+        DeoProfileError::UnknownProfileBackend(profile, error) => format!("Profile: {profile} has an unknown Backend: {error}, use handbrake or ffmpeg"),
+        DeoProfileError::UnknownProfileFileFormat(profile, error) => format!("Profile: {profile} has an unknown FileFormat: {error}, use av_mp4, av_mkv or av_webm"),
+        DeoProfileError::UnknownProfileExtension(profile, error) => format!("Profile: {profile} has an unknown Extension: {error}, use mp4, m4v, mkv or webm"),
         DeoProfileError::ProfilesDirDoesNotExist(error) => format!("Profile directory does not exist: {error}"),
-        DeoProfileError::CouldNotFindHomeDir => "Could not find home directory".to_owned(),
         DeoProfileError::NoProfilesFound(error) => format!("Could not find any profiles at: {error}"),
       };
 
@@ -76,17 +82,141 @@ impl HandbrakeCommand {
 
 #[derive(Debug)]
 pub enum DeoEncodingError {
-  CouldNotRemoveLogFile(LogFile, String),
   CouldNotOpenLogFile(LogFile, String),
-  FailedToSpawnHandbrake(HandbrakeCommand, String),
+  CouldNotWriteLogFile(LogFile, String),
+  FailedToSpawnEncoder(HandbrakeCommand, String),
+  CouldNotUpdateJournal(DeoJournalError),
+  CouldNotInstallSignalHandler(String),
+  CouldNotWriteReport(FileName, String),
+  CouldNotCreateScratchDir(DirName, String),
+  NotEnoughFreeSpace(String, Vec<String>),
 }
 
 impl fmt::Display for DeoEncodingError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let item = match self {
-          DeoEncodingError::CouldNotRemoveLogFile(log_file, error) => format!("Could not remove log file: {} due to: {}", log_file.0, error),
           DeoEncodingError::CouldNotOpenLogFile(log_file, error) => format!("Could not open log file: {} due to: {}", log_file.0, error),
-          DeoEncodingError::FailedToSpawnHandbrake(cmd, error) => format!("Could not spawn handbrake command: '{}' due to: {}", cmd.0, error),
+          DeoEncodingError::CouldNotWriteLogFile(log_file, error) => format!("Could not write to log file: {} due to: {}", log_file.0, error),
+          DeoEncodingError::FailedToSpawnEncoder(cmd, error) => format!("Could not spawn encoder command: '{}' due to: {}", cmd.0, error),
+          DeoEncodingError::CouldNotUpdateJournal(error) => format!("Could not update journal: {}", error),
+          DeoEncodingError::CouldNotInstallSignalHandler(error) => format!("Could not install the Ctrl-C handler due to: {}", error),
+          DeoEncodingError::CouldNotWriteReport(report, error) => format!("Could not write report: {} due to: {}", report, error),
+          DeoEncodingError::CouldNotCreateScratchDir(dir, error) => format!("Could not create scratch directory: {} due to: {}", dir, error),
+          DeoEncodingError::NotEnoughFreeSpace(min_free_space, filesystems) => format!("Not enough free space to encode the queue and keep {} free: {}. Free some space and use `deo resume`, or use --no-space-check to encode anyway", min_free_space, filesystems.join(", ")),
+        };
+
+        write!(f, "{}", item)
+    }
+}
+
+#[derive(Debug)]
+pub enum DeoSelectionError {
+  UnknownProfile(String, Vec<String>),
+  UnknownSession(String, Vec<String>),
+  MissingFallbackProfile(String, String, Vec<String>),
+  FallbackContainerMismatch(String, String, String, String),
+}
+
+impl fmt::Display for DeoSelectionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let item = match self {
+          DeoSelectionError::UnknownProfile(profile, available) => format!("Unknown profile: {}, available profiles are: {}", profile, available.join(", ")),
+          DeoSelectionError::UnknownSession(session, available) => format!("Unknown session: {}, available sessions are: {}", session, available.join(", ")),
+          DeoSelectionError::MissingFallbackProfile(profile, fallback, available) => format!("Unknown fallback profile: {} for profile: {}, available profiles are: {}", fallback, profile, available.join(", ")),
+          DeoSelectionError::FallbackContainerMismatch(profile, container, fallback, fallback_container) => format!("Fallback profile: {} ({}) encodes to a different container than profile: {} ({}), a fallback profile must use the same container", fallback, fallback_container, profile, container),
+        };
+
+        write!(f, "{}", item)
+    }
+}
+
+#[derive(Debug)]
+pub enum DeoRulesError {
+  CouldNotReadRules(FileName, String),
+  CouldNotDecodeRules(FileName, String),
+  InvalidRulePattern(String, String),
+  RuleWithoutPatterns(String),
+  UnknownRuleProfile(String, String, Vec<String>),
+}
+
+impl fmt::Display for DeoRulesError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let item = match self {
+          DeoRulesError::CouldNotReadRules(rules, error) => format!("Could not read rules file: {}, due to: {}", rules, error),
+          DeoRulesError::CouldNotDecodeRules(rules, error) => format!("Could not decode rules file: {}, due to: {}", rules, error),
+          DeoRulesError::InvalidRulePattern(rule, error) => format!("Rule: {} has an invalid pattern: {}", rule, error),
+          DeoRulesError::RuleWithoutPatterns(rule) => format!("Rule: {} needs at least one of series, movie, tvdb, type or session", rule),
+          DeoRulesError::UnknownRuleProfile(rule, profile, available) => format!("Rule: {} has an unknown profile: {}, available profiles are: {}", rule, profile, available.join(", ")),
+        };
+
+        write!(f, "{}", item)
+    }
+}
+
+#[derive(Debug)]
+pub enum DeoJournalError {
+  CouldNotFindHomeDir,
+  NoJournalFound(FileName),
+  CouldNotReadJournal(FileName, String),
+  CouldNotDecodeJournal(FileName, String),
+  CouldNotWriteJournal(FileName, String),
+  UnfinishedJournal(FileName, usize),
+}
+
+impl fmt::Display for DeoJournalError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let item = match self {
+          DeoJournalError::CouldNotFindHomeDir => "Could not find home directory".to_owned(),
+          DeoJournalError::NoJournalFound(journal) => format!("Could not find a journal to resume at: {}", journal),
+          DeoJournalError::CouldNotReadJournal(journal, error) => format!("Could not read journal: {}, due to: {}", journal, error),
+          DeoJournalError::CouldNotDecodeJournal(journal, error) => format!("Could not decode journal: {}, due to: {}", journal, error),
+          DeoJournalError::CouldNotWriteJournal(journal, error) => format!("Could not write journal: {}, due to: {}", journal, error),
+          DeoJournalError::UnfinishedJournal(journal, count) => format!("The journal at: {} has {} unfinished files, use `deo resume` to finish them first", journal, count),
+        };
+
+        write!(f, "{}", item)
+    }
+}
+
+#[derive(Debug)]
+pub enum DeoConfigError {
+  CouldNotFindHomeDir,
+  ConfigFileDoesNotExist(FileName),
+  CouldNotReadConfig(FileName, String),
+  CouldNotDecodeConfig(FileName, String),
+  ArchiveDirRequired,
+}
+
+impl fmt::Display for DeoConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let item = match self {
+          DeoConfigError::CouldNotFindHomeDir => "Could not find home directory".to_owned(),
+          DeoConfigError::ConfigFileDoesNotExist(config) => format!("Config file does not exist: {}", config),
+          DeoConfigError::CouldNotReadConfig(config, error) => format!("Could not read config file: {}, due to: {}", config, error),
+          DeoConfigError::CouldNotDecodeConfig(config, error) => format!("Could not decode config file: {}, due to: {}", config, error),
+          DeoConfigError::ArchiveDirRequired => "Archiving sources needs an archive directory. Use --archive-dir or set archive_dir in the config file".to_owned(),
+        };
+
+        write!(f, "{}", item)
+    }
+}
+
+#[derive(Debug)]
+pub enum DeoScanError {
+  FailedToSpawnHandbrake(HandbrakeCommand, String),
+  ScanFailed(FileName, Option<i32>),
+  NoTitleSetFound(FileName),
+  CouldNotDecodeTitleSet(FileName, String),
+}
+
+impl fmt::Display for DeoScanError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let item = match self {
+          DeoScanError::FailedToSpawnHandbrake(cmd, error) => format!("Could not spawn handbrake command: '{}' due to: {}", cmd.0, error),
+          DeoScanError::ScanFailed(file, Some(code)) => format!("Could not scan: {}, handbrake exited with: {}", file, code),
+          DeoScanError::ScanFailed(file, None) => format!("Could not scan: {}, handbrake was terminated", file),
+          DeoScanError::NoTitleSetFound(file) => format!("Could not find any titles in: {}", file),
+          DeoScanError::CouldNotDecodeTitleSet(file, error) => format!("Could not decode the titles of: {}, due to: {}", file, error),
         };
 
         write!(f, "{}", item)
@@ -215,7 +215,7 @@ mod tests {
       let encode_file_path = "/Some/Path/Rips/session2/renames/encode_dir.txt";
       assert_eq!(ENCODE_FILE_REG.is_match(encode_file_path), true);
 
-      let (considered_str, [session]) = ENCODE_FILE_REG.captures(&encode_file_path).unwrap().extract();
+      let (considered_str, [session]) = ENCODE_FILE_REG.captures(encode_file_path).unwrap().extract();
       assert_eq!(considered_str, "session2/renames/encode_dir.txt");
       assert_eq!(session, "session2")
     }
@@ -269,28 +269,24 @@ mod tests {
             session: session_id.clone(),
             episode: "S01E01".to_string(),
             mkv_file: "S01E01 - Exodus.mkv".to_string(),
-            mp4_file: "S01E01 - Exodus.mp4".to_string(),
           },
           TVSeriesRenameFile {
             path: format!("{}/Rips/session1/renames/S01E02 - The Unholy Alliance.mkv", &test_path).into(),
             session: session_id.clone(),
             episode: "S01E02".to_string(),
             mkv_file: "S01E02 - The Unholy Alliance.mkv".to_string(),
-            mp4_file: "S01E02 - The Unholy Alliance.mp4".to_string(),
           },
           TVSeriesRenameFile {
             path: format!("{}/Rips/session1/renames/S01E03 - Berbils.mkv", &test_path).into(),
             session: session_id.clone(),
             episode: "S01E03".to_string(),
             mkv_file: "S01E03 - Berbils.mkv".to_string(),
-            mp4_file: "S01E03 - Berbils.mp4".to_string(),
           },
           TVSeriesRenameFile {
             path: format!("{}/Rips/session1/renames/S01E04-E05 - The Saga.mkv", &test_path).into(),
             session: session_id.clone(),
             episode: "S01E04-E05".to_string(),
             mkv_file: "S01E04-E05 - The Saga.mkv".to_string(),
-            mp4_file: "S01E04-E05 - The Saga.mp4".to_string(),
           },
         ];
 
@@ -317,7 +313,7 @@ mod tests {
 
     fn create_encode_dir_file(test_path: &str, encode_dir_content_path: &str, session_path: &str) {
       let encode_file_name = Path::new(test_path).join(session_path).join("encode_dir.txt");
-      let mut encode_file = File::create(&encode_file_name).expect(&format!("Could not create file: {}", encode_file_name.to_string_lossy()));
+      let mut encode_file = File::create(&encode_file_name).unwrap_or_else(|_| panic!("Could not create file: {}", encode_file_name.to_string_lossy()));
       let buf: String = Path::new(test_path).join(encode_dir_content_path).to_string_lossy().to_string();
       encode_file.write_all(buf.as_bytes()).expect("Could not write encode file content");
       encode_file.flush().expect("Could not flush encode file write");
@@ -343,7 +339,6 @@ mod tests {
             path: format!("{}/Rips/session5/renames/Star Wars - {{tvdb-71}}.mkv", &test_path).into(),
             session: session_id.clone(),
             mkv_file: "Star Wars - {tvdb-71}.mkv".to_string(),
-            mp4_file: "Star Wars - {tvdb-71}.mp4".to_string(),
           }
         ];
 
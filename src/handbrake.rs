@@ -1,5 +1,41 @@
+mod backend;
+mod command;
+mod disk_space;
+mod dry_run;
+mod encode_logs;
 mod encoder;
+mod existing_output;
+mod hooks;
+mod interrupt;
 mod model;
+mod options;
+mod partial_output;
+mod run_log;
+mod scan;
+mod scan_report;
+mod source_disposition;
+mod summary;
+mod verify;
 
-pub use encoder::encode as encoder_with_handbrake;
+pub use backend::Encoders as Encoders;
+pub use backend::FfmpegCli as FfmpegCli;
+pub use command::HandbrakeCli as HandbrakeCli;
+pub use disk_space::gib as gib;
+pub use dry_run::dry_run as dry_run;
+pub use encoder::encode as encode;
+pub use existing_output::OnExisting as OnExisting;
+pub use hooks::Hooks as Hooks;
+pub use interrupt::install as install_interrupt_handler;
+pub use interrupt::Interrupt as Interrupt;
 pub use model::HandbrakeInfo as HandbrakeInfo;
+pub use model::EncodeJob as EncodeJob;
+pub use model::EncodeInput as EncodeInput;
+pub use options::EncodeOptions as EncodeOptions;
+pub use run_log::encoded_inputs as encoded_inputs;
+pub use scan_report::ScanFormat as ScanFormat;
+pub use scan_report::scan_sources as scan_sources;
+pub use source_disposition::DisposeAfter as DisposeAfter;
+pub use source_disposition::SourceAction as SourceAction;
+pub use source_disposition::SourceDisposition as SourceDisposition;
+pub use summary::Report as Report;
+pub use summary::ReportFormat as ReportFormat;
@@ -0,0 +1,44 @@
+mod ffmpeg;
+mod handbrake_cli;
+
+use std::path::Path;
+
+use crate::hb_output_parser::EncoderState;
+use crate::profiles::{Backend, ProfileConfigItem};
+use super::command::{EncodeCommand, HandbrakeCli};
+
+pub use ffmpeg::FfmpegCli as FfmpegCli;
+
+/// A program that encodes files with a profile.
+pub trait Encoder: Send + Sync {
+  /// The command that encodes the input file into the output file.
+  fn encode_command(&self, profile: &ProfileConfigItem, input_file: &Path, output_file: &Path) -> EncodeCommand;
+
+  /// A parser for the progress the encode command writes to stdout.
+  fn progress_parser(&self, input_file: &Path) -> Box<dyn ProgressParser>;
+
+  /// The duration of a media file in seconds.
+  fn duration(&self, file: &Path) -> Result<f64, String>;
+}
+
+/// Reads the progress of an encode one line of output at a time. Progress of every encoder is reported as handbrake
+/// states, so that all encoders share the same progress bars and stall detection.
+pub trait ProgressParser {
+  fn progress(&mut self, line: &str) -> Option<EncoderState>;
+}
+
+/// The encoders that profiles can target.
+#[derive(Debug, Clone, Default)]
+pub struct Encoders {
+  pub handbrake: HandbrakeCli,
+  pub ffmpeg: FfmpegCli,
+}
+
+impl Encoders {
+  pub fn for_profile(&self, profile: &ProfileConfigItem) -> &dyn Encoder {
+    match profile.backend() {
+      Backend::Handbrake => &self.handbrake,
+      Backend::Ffmpeg => &self.ffmpeg,
+    }
+  }
+}
@@ -0,0 +1,201 @@
+use std::ffi::OsString;
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+use crate::hb_output_parser::{EncoderState, WorkDone, Working};
+use crate::profiles::ProfileConfigItem;
+use super::{Encoder, ProgressParser};
+use super::super::command::EncodeCommand;
+
+const FFMPEG: &str = "ffmpeg";
+const FFPROBE: &str = "ffprobe";
+
+/// The ffmpeg executable, and the ffprobe executable that is used to find the duration of files.
+#[derive(Debug, Clone)]
+pub struct FfmpegCli {
+  pub executable: String,
+  pub ffprobe: String,
+}
+
+impl Default for FfmpegCli {
+  fn default() -> Self {
+    Self {
+      executable: FFMPEG.to_owned(),
+      ffprobe: FFPROBE.to_owned(),
+    }
+  }
+}
+
+impl Encoder for FfmpegCli {
+  /// The arguments of the profile go between the input and the output, so they apply to the output. Progress is written
+  /// to stdout as key=value lines, while the usual ffmpeg output goes to stderr.
+  fn encode_command(&self, profile: &ProfileConfigItem, input_file: &Path, output_file: &Path) -> EncodeCommand {
+    let input_args: Vec<OsString> =
+      vec![
+        "-hide_banner".into(),
+        "-nostdin".into(),
+        "-y".into(),
+        "-i".into(),
+        input_file.into(),
+      ];
+
+    let progress_args: Vec<OsString> =
+      vec![
+        "-progress".into(),
+        "pipe:1".into(),
+        "-nostats".into(),
+        output_file.into(),
+      ];
+
+    let args =
+      input_args
+        .into_iter()
+        .chain(profile.arguments().iter().map(OsString::from))
+        .chain(progress_args)
+        .collect();
+
+    EncodeCommand::new(&self.executable, args, output_file)
+  }
+
+  fn progress_parser(&self, input_file: &Path) -> Box<dyn ProgressParser> {
+    // Without a duration the progress bar can't move, but the rate is still shown
+    Box::new(FfmpegProgress::new(self.duration(input_file).ok()))
+  }
+
+  fn duration(&self, file: &Path) -> Result<f64, String> {
+    let output =
+      Command::new(&self.ffprobe)
+        .args(["-v", "error", "-show_entries", "format=duration", "-of", "default=noprint_wrappers=1:nokey=1"])
+        .arg(file)
+        .stderr(Stdio::null())
+        .output()
+        .map_err(|e| format!("could not run {}: {}", self.ffprobe, e))?;
+
+    if !output.status.success() {
+      return Err(format!("{} could not read: {}", self.ffprobe, file.to_string_lossy()))
+    }
+
+    let duration = String::from_utf8_lossy(&output.stdout).trim().to_owned();
+    duration
+      .parse::<f64>()
+      .map_err(|_| format!("{} returned an invalid duration: {}", self.ffprobe, duration))
+  }
+}
+
+/// Parses the key=value blocks written by `ffmpeg -progress`. Each block ends with a `progress=continue` line, or
+/// `progress=end` once the encode has finished.
+#[derive(Debug, Default)]
+struct FfmpegProgress {
+  duration: Option<f64>,
+  out_time_us: Option<i64>,
+  fps: f64,
+  speed: f64,
+}
+
+impl FfmpegProgress {
+  fn new(duration: Option<f64>) -> Self {
+    Self {
+      duration: duration.filter(|duration| *duration > 0.0),
+      ..Self::default()
+    }
+  }
+
+  fn working(&self) -> Working {
+    let encoded_seconds = self.out_time_us.unwrap_or(0).max(0) as f64 / 1_000_000.0;
+
+    let progress =
+      self
+        .duration
+        .map_or(0.0, |duration| (encoded_seconds / duration).clamp(0.0, 1.0));
+
+    let eta_seconds =
+      self
+        .duration
+        .filter(|_| self.speed > 0.0)
+        .map_or(0, |duration| ((duration - encoded_seconds).max(0.0) / self.speed) as i64);
+
+    Working {
+      progress,
+      pass: 1,
+      pass_count: 1,
+      rate: self.fps,
+      rate_avg: self.fps,
+      eta_seconds,
+    }
+  }
+}
+
+impl ProgressParser for FfmpegProgress {
+  fn progress(&mut self, line: &str) -> Option<EncoderState> {
+    let (key, value) = line.split_once('=')?;
+    let value = value.trim();
+
+    match key.trim() {
+      "fps" => self.fps = value.parse().unwrap_or(0.0),
+      // Despite its name, out_time_ms is also in microseconds. Both are N/A until the first frame is written
+      "out_time_us" | "out_time_ms" => self.out_time_us = value.parse().ok().or(self.out_time_us),
+      "speed" => self.speed = value.trim_end_matches('x').parse().unwrap_or(0.0),
+      "progress" if value == "end" => return Some(EncoderState::WorkDone(WorkDone { error: 0 })),
+      "progress" => return Some(EncoderState::Working(self.working())),
+      _ => (),
+    }
+
+    None
+  }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use crate::profiles::{Backend, Container};
+
+    const PROGRESS_OUTPUT: &str = include_str!("../../../data/ffmpeg/progress_output.txt");
+
+    fn states(duration: Option<f64>) -> Vec<EncoderState> {
+      let mut parser = FfmpegProgress::new(duration);
+      PROGRESS_OUTPUT
+        .lines()
+        .filter_map(|line| parser.progress(line))
+        .collect()
+    }
+
+    #[test]
+    fn parses_captured_progress_output() {
+      let expected =
+        vec![
+          EncoderState::Working(Working { progress: 0.0, pass: 1, pass_count: 1, rate: 0.0, rate_avg: 0.0, eta_seconds: 0 }),
+          EncoderState::Working(Working { progress: 0.25, pass: 1, pass_count: 1, rate: 96.5, rate_avg: 96.5, eta_seconds: 375 }),
+          EncoderState::Working(Working { progress: 0.5, pass: 1, pass_count: 1, rate: 97.2, rate_avg: 97.2, eta_seconds: 250 }),
+          EncoderState::WorkDone(WorkDone { error: 0 }),
+        ];
+
+      assert_eq!(states(Some(1000.0)), expected)
+    }
+
+    #[test]
+    fn reports_rate_without_a_duration() {
+      let working_states: Vec<(f64, f64, i64)> =
+        states(None)
+          .into_iter()
+          .filter_map(|state| match state {
+            EncoderState::Working(working) => Some((working.progress, working.rate, working.eta_seconds)),
+            _ => None,
+          })
+          .collect();
+
+      assert_eq!(working_states, vec![(0.0, 0.0, 0), (0.0, 96.5, 0), (0.0, 97.2, 0)])
+    }
+
+    #[test]
+    fn places_profile_arguments_between_input_and_output() {
+      let profile = ProfileConfigItem::new("x265", Backend::Ffmpeg, Container::Mp4).with_arguments(&["-c:v", "libx265"]);
+
+      let command = FfmpegCli::default().encode_command(&profile, Path::new("/Rips/in.mkv"), Path::new("/Encodes/out.mp4"));
+
+      assert_eq!(
+        command.to_string(),
+        "ffmpeg -hide_banner -nostdin -y -i /Rips/in.mkv -c:v libx265 -progress pipe:1 -nostats /Encodes/out.mp4"
+      )
+    }
+}
@@ -0,0 +1,37 @@
+use std::path::Path;
+
+use crate::hb_output_parser::{EncoderState, Output, OutputParser};
+use crate::profiles::ProfileConfigItem;
+use super::{Encoder, ProgressParser};
+use super::super::command::{EncodeCommand, HandbrakeCli};
+use super::super::scan;
+
+impl Encoder for HandbrakeCli {
+  fn encode_command(&self, profile: &ProfileConfigItem, input_file: &Path, output_file: &Path) -> EncodeCommand {
+    EncodeCommand::handbrake(self, profile, input_file, output_file)
+  }
+
+  fn progress_parser(&self, _input_file: &Path) -> Box<dyn ProgressParser> {
+    Box::new(OutputParser::new())
+  }
+
+  fn duration(&self, file: &Path) -> Result<f64, String> {
+    scan::scan(self, file)
+      .map_err(|e| e.to_string())
+      .and_then(|title_set| {
+        title_set
+          .first_title()
+          .map(|title| title.duration.as_secs_f64())
+          .ok_or_else(|| "no titles found".to_owned())
+      })
+  }
+}
+
+impl ProgressParser for OutputParser {
+  fn progress(&mut self, line: &str) -> Option<EncoderState> {
+    match self.parse(line) {
+      Some(Output::Progress(state)) => Some(state),
+      _ => None,
+    }
+  }
+}
@@ -0,0 +1,130 @@
+use std::ffi::OsString;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::profiles::ProfileConfigItem;
+
+const HANDBRAKE_CLI: &str = "handbrakecli";
+
+/// The handbrake executable and the arguments that are passed to every invocation of it.
+#[derive(Debug, Clone)]
+pub struct HandbrakeCli {
+  pub executable: String,
+  pub global_args: Vec<String>,
+}
+
+impl Default for HandbrakeCli {
+  fn default() -> Self {
+    Self {
+      executable: HANDBRAKE_CLI.to_owned(),
+      global_args: vec![],
+    }
+  }
+}
+
+impl HandbrakeCli {
+  /// A command that scans the titles of a file and prints them as JSON, without encoding anything.
+  pub fn scan_command(&self, input_file: &Path) -> Command {
+    let mut cmd = Command::new(&self.executable);
+    cmd
+      .args(&self.global_args)
+      .args(["--scan", "--json", "-i"])
+      .arg(input_file);
+    cmd
+  }
+}
+
+/// A single encoder invocation that encodes one input file into an output file.
+#[derive(Debug, Clone)]
+pub struct EncodeCommand {
+  program: String,
+  args: Vec<OsString>,
+  output_file: PathBuf,
+}
+
+impl EncodeCommand {
+  pub fn new(program: &str, args: Vec<OsString>, output_file: &Path) -> Self {
+    Self {
+      program: program.to_owned(),
+      args,
+      output_file: output_file.to_owned(),
+    }
+  }
+
+  pub fn handbrake(handbrake_cli: &HandbrakeCli, profile: &ProfileConfigItem, input_file: &Path, output_file: &Path) -> Self {
+    let global_args =
+      handbrake_cli
+        .global_args
+        .iter()
+        .map(OsString::from);
+
+    let encode_args: Vec<OsString> =
+      vec![
+        "--preset-import-file".into(),
+        profile.full_path().into(),
+        "-Z".into(),
+        profile.preset_name().into(),
+        "--json".into(),
+        "-i".into(),
+        input_file.into(),
+        "-o".into(),
+        output_file.into(),
+      ];
+
+    Self::new(&handbrake_cli.executable, global_args.chain(encode_args).collect(), output_file)
+  }
+
+  pub fn output_file(&self) -> &Path {
+    &self.output_file
+  }
+
+  pub fn command(&self) -> Command {
+    let mut cmd = Command::new(&self.program);
+    cmd.args(&self.args);
+    cmd
+  }
+}
+
+/// Displays the command as it would be typed into a shell, quoting any arguments that need it.
+impl fmt::Display for EncodeCommand {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let args: Vec<String> =
+      self
+        .args
+        .iter()
+        .map(|arg| shell_quote(&arg.to_string_lossy()))
+        .collect();
+
+    write!(f, "{} {}", self.program, args.join(" "))
+  }
+}
+
+fn shell_quote(arg: &str) -> String {
+  let is_safe = !arg.is_empty() && arg.chars().all(|c| c.is_ascii_alphanumeric() || "-_./=:,+@%".contains(c));
+
+  if is_safe {
+    arg.to_owned()
+  } else {
+    format!("'{}'", arg.replace('\'', r"'\''"))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn shell_quote_leaves_safe_arguments_alone() {
+      assert_eq!(shell_quote("--preset-import-file"), "--preset-import-file");
+      assert_eq!(shell_quote("/some/path/file.mkv"), "/some/path/file.mkv");
+    }
+
+    #[test]
+    fn shell_quote_quotes_arguments_with_spaces_and_quotes() {
+      assert_eq!(shell_quote("S01E01 - Exodus.mkv"), "'S01E01 - Exodus.mkv'");
+      assert_eq!(shell_quote("Bob's Burgers"), r"'Bob'\''s Burgers'");
+      assert_eq!(shell_quote(""), "''");
+    }
+}
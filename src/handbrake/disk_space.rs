@@ -0,0 +1,205 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+
+use indicatif::HumanBytes;
+use serde::Deserialize;
+
+use super::EncodeJob;
+use super::run_log::{self, FileStatus};
+
+const BYTES_PER_GIB: u64 = 1024 * 1024 * 1024;
+
+pub fn gib(gib: u64) -> u64 {
+  gib * BYTES_PER_GIB
+}
+
+/// Estimates the size of an output from the size of its source, using the size factor (output size / source size)
+/// that each profile has achieved in earlier runs. This is the inverse of the compression ratio in the summary.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SizeEstimator {
+  size_factors: BTreeMap<String, f64>,
+  default_factor: f64,
+}
+
+/// The parts of a run log record that show how well a profile compresses.
+#[derive(Deserialize)]
+struct HistoryRecord {
+  profile: String,
+  status: FileStatus,
+  input_size: Option<u64>,
+  output_size: Option<u64>,
+}
+
+impl SizeEstimator {
+  /// Learn the size factor of each profile from the completed files in every run log under the log directory. Profiles
+  /// without any history use the default factor.
+  pub fn from_history(log_dir: &Path, default_factor: f64) -> Self {
+    let mut sizes: BTreeMap<String, (u64, u64)> = BTreeMap::new();
+
+    for record in run_log::read_history::<HistoryRecord>(log_dir) {
+      if let (FileStatus::Completed, Some(input_size), Some(output_size)) = (record.status, record.input_size, record.output_size) {
+        let (total_input, total_output) = sizes.entry(record.profile).or_default();
+        *total_input += input_size;
+        *total_output += output_size;
+      }
+    }
+
+    let size_factors =
+      sizes
+        .into_iter()
+        .filter(|(_, (input_size, _))| *input_size > 0)
+        .map(|(profile, (input_size, output_size))| (profile, output_size as f64 / input_size as f64))
+        .collect();
+
+    Self { size_factors, default_factor }
+  }
+
+  /// The output size of a profile as a fraction of its source size, so 0.25 for an output a quarter of the size.
+  pub fn size_factor(&self, profile: &str) -> f64 {
+    self.size_factors.get(profile).copied().unwrap_or(self.default_factor)
+  }
+
+  /// The estimated size of the output of a job, which is 0 when its source can't be read.
+  pub fn estimate(&self, job: &EncodeJob) -> u64 {
+    let input_size = std::fs::metadata(&job.input.mkv_path).map_or(0, |metadata| metadata.len());
+    (input_size as f64 * self.size_factor(&job.profile.to_string())).ceil() as u64
+  }
+}
+
+/// The space needed on a filesystem for the outputs that will be written to it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FilesystemSpace {
+  /// The first output directory on the filesystem
+  pub dir: PathBuf,
+  pub required: u64,
+  /// None when the free space of the filesystem can't be found
+  pub available: Option<u64>,
+}
+
+impl FilesystemSpace {
+  pub fn is_enough(&self, min_free_space: u64) -> bool {
+    self.available.is_none_or(|available| available >= self.required.saturating_add(min_free_space))
+  }
+}
+
+impl fmt::Display for FilesystemSpace {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let available = self.available.map_or("unknown".to_owned(), |available| HumanBytes(available).to_string());
+    write!(f, "{} needs about {} and has {} free", self.dir.to_string_lossy(), HumanBytes(self.required), available)
+  }
+}
+
+/// The estimated space needed on each filesystem the jobs will be encoded to, and how much is free. Partial outputs in a
+/// scratch directory on another filesystem need space there too, but only for the encodes that run at the same time,
+/// as each is deleted once it has been copied to its output.
+pub fn required_space(jobs: &[&EncodeJob], estimator: &SizeEstimator, scratch_dir: Option<&Path>, parallel_jobs: usize) -> Vec<FilesystemSpace> {
+  let mut filesystems: Vec<(Option<u64>, FilesystemSpace)> = vec![];
+  let scratch_dir = scratch_dir.map(existing_ancestor);
+  let scratch_device = scratch_dir.as_deref().and_then(device);
+  let mut scratch_estimates = vec![];
+
+  for job in jobs {
+    let dir = existing_ancestor(&job.encode_dir_path);
+    let device = device(&dir);
+    let estimate = estimator.estimate(job);
+
+    // A partial output on the same filesystem as its output is renamed into place, so it's only counted once
+    if scratch_dir.as_ref().is_some_and(|scratch_dir| !is_same_filesystem((device, &dir), (scratch_device, scratch_dir))) {
+      scratch_estimates.push(estimate)
+    }
+
+    add_required(&mut filesystems, dir, device, estimate);
+  }
+
+  if let Some(scratch_dir) = scratch_dir.filter(|_| !scratch_estimates.is_empty()) {
+    add_required(&mut filesystems, scratch_dir, scratch_device, largest_total(scratch_estimates, parallel_jobs));
+  }
+
+  filesystems
+    .into_iter()
+    .map(|(_, space)| space)
+    .collect()
+}
+
+fn add_required(filesystems: &mut Vec<(Option<u64>, FilesystemSpace)>, dir: PathBuf, device: Option<u64>, required: u64) {
+  match filesystems.iter_mut().find(|(other_device, space)| is_same_filesystem((device, &dir), (*other_device, &space.dir))) {
+    Some((_, space)) => space.required += required,
+    None => {
+      let available = available_space(&dir);
+      filesystems.push((device, FilesystemSpace { dir, required, available }))
+    },
+  }
+}
+
+fn is_same_filesystem((device, dir): (Option<u64>, &Path), (other_device, other_dir): (Option<u64>, &Path)) -> bool {
+  (device.is_some() && device == other_device) || dir == other_dir
+}
+
+fn device(dir: &Path) -> Option<u64> {
+  std::fs::metadata(dir).ok().map(|metadata| metadata.dev())
+}
+
+/// The total of the largest estimates, which is the most that a number of encodes running at once can need.
+fn largest_total(mut estimates: Vec<u64>, count: usize) -> u64 {
+  estimates.sort_unstable_by(|a, b| b.cmp(a));
+  estimates.into_iter().take(count.max(1)).sum()
+}
+
+/// The free space on the filesystem that holds a path, which needn't exist yet.
+pub fn available_space(path: &Path) -> Option<u64> {
+  fs2::available_space(existing_ancestor(path)).ok()
+}
+
+/// Output directories are created by the encoder, so the space of a missing directory is that of its closest parent.
+fn existing_ancestor(path: &Path) -> PathBuf {
+  path
+    .ancestors()
+    .find(|ancestor| ancestor.exists())
+    .unwrap_or(path)
+    .to_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn learns_the_size_factor_of_each_profile_from_completed_files() {
+      let estimator = SizeEstimator::from_history(Path::new("data/logs"), 1.0);
+
+      assert_eq!(estimator.size_factor("Fast 1080p"), 0.25);
+      assert_eq!(estimator.size_factor("HQ 1080p"), 0.5);
+    }
+
+    #[test]
+    fn uses_the_default_factor_without_history() {
+      assert_eq!(SizeEstimator::from_history(Path::new("data/logs"), 0.8).size_factor("Super HQ 2160p"), 0.8);
+      assert_eq!(SizeEstimator::from_history(Path::new("data/no-such-logs"), 1.0).size_factor("Fast 1080p"), 1.0);
+    }
+
+    #[test]
+    fn keeps_the_minimum_free_space_free() {
+      let space = FilesystemSpace { dir: PathBuf::from("/media/Encodes"), required: gib(10), available: Some(gib(12)) };
+
+      assert!(space.is_enough(gib(2)));
+      assert!(!space.is_enough(gib(3)));
+      assert!(FilesystemSpace { available: None, ..space }.is_enough(gib(3)));
+    }
+
+    #[test]
+    fn the_scratch_dir_only_needs_space_for_the_running_encodes() {
+      assert_eq!(largest_total(vec![gib(2), gib(8), gib(4)], 2), gib(12));
+      assert_eq!(largest_total(vec![gib(2), gib(8), gib(4)], 5), gib(14));
+      assert_eq!(largest_total(vec![], 2), 0);
+    }
+
+    #[test]
+    fn groups_dirs_by_their_filesystem() {
+      assert!(is_same_filesystem((device(Path::new("data")), Path::new("data")), (device(Path::new("src")), Path::new("src"))));
+      assert!(is_same_filesystem((None, Path::new("/scratch")), (None, Path::new("/scratch"))));
+      assert!(!is_same_filesystem((None, Path::new("/scratch")), (None, Path::new("/media"))));
+    }
+}
@@ -0,0 +1,30 @@
+use std::path::Path;
+
+use console::style;
+
+use crate::user_selection::UserSelection;
+use super::{EncodeInput, HandbrakeInfo};
+use super::backend::Encoders;
+use super::partial_output::partial_path;
+
+/// Print the encoder command and output path for every file that would be encoded, without running anything.
+pub fn dry_run(selections: Vec<UserSelection>, encoders: &Encoders, extension: Option<&str>, scratch_dir: Option<&Path>) {
+  println!("dry run, nothing will be encoded:");
+
+  let handbrake_infos: Vec<HandbrakeInfo> = selections.into_iter().map(|us| HandbrakeInfo::new(us, extension)).collect();
+  for handbrake_info in handbrake_infos {
+    println!();
+    println!("{}", style(handbrake_info.encode_dir_path.to_string_lossy()).underlined());
+
+    for EncodeInput { input, profile, output_file } in handbrake_info.input_files {
+      let output_file = handbrake_info.encode_dir_path.join(output_file);
+      let partial_file = partial_path(&output_file, &handbrake_info.session_id, scratch_dir);
+      let encode_command = encoders.for_profile(&profile).encode_command(&profile, &input.mkv_path, &partial_file);
+
+      println!(" - {} with {}", input.mkv_file, style(&profile).blue());
+      println!("   output: {}", output_file.to_string_lossy());
+      println!("   encoded to: {}", encode_command.output_file().to_string_lossy());
+      println!("   command: {}", encode_command);
+    }
+  }
+}
@@ -0,0 +1,52 @@
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+
+use super::EncodeJob;
+
+/// Where the output of handbrake is written for a single file.
+#[derive(Debug, Clone)]
+pub struct EncodeLogs {
+  pub stderr_log: PathBuf,
+  pub stdout_log: Option<PathBuf>,
+}
+
+impl EncodeLogs {
+  pub fn new(log_dir: &Path, job: &EncodeJob, attempt: u32, keep_json_output: bool) -> Self {
+    let name = log_name(job, attempt);
+    let stderr_log = log_dir.join(format!("{name}.log"));
+    let stdout_log = keep_json_output.then(|| log_dir.join(format!("{name}.json.log")));
+
+    Self {
+      stderr_log,
+      stdout_log,
+    }
+  }
+}
+
+/// Log files are named after the session and input file, as episode names can repeat across sessions. Retries get a log
+/// of their own.
+fn log_name(job: &EncodeJob, attempt: u32) -> String {
+  let stem =
+    Path::new(&job.input.mkv_file)
+      .file_stem()
+      .map(|s| s.to_string_lossy().to_string())
+      .unwrap_or_else(|| job.input.mkv_file.clone());
+
+  if attempt > 1 {
+    format!("{} - {} (attempt {})", job.session_id, stem, attempt)
+  } else {
+    format!("{} - {}", job.session_id, stem)
+  }
+}
+
+/// The last `count` lines of a log file, or nothing if it can't be read.
+pub fn tail(log_file: &Path, count: usize) -> Vec<String> {
+  let lines: Vec<String> =
+    File::open(log_file)
+      .map(|file| BufReader::new(file).lines().map_while(Result::ok).collect())
+      .unwrap_or_default();
+
+  let start = lines.len().saturating_sub(count);
+  lines[start..].to_vec()
+}
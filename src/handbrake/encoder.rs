@@ -1,39 +1,99 @@
-use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
-use std::fs::OpenOptions;
-use std::io::Write;
-use std::path::Path;
-use std::process::{Command, Stdio};
-
-use crate::error::{DeoEncodingError, HandbrakeCommand, LogFile};
-use crate::user_selection::UserSelection;
-use crate::hb_output_parser::{parse, Output};
-use super::HandbrakeInfo;
-
-pub fn encode(selections: Vec<UserSelection>) -> Result<(), DeoEncodingError> {
+use indicatif::{FormattedDuration, HumanBytes, MultiProgress, ProgressBar, ProgressStyle};
+use console::style;
+use std::collections::VecDeque;
+use chrono::Local;
+use std::fs::File;
+use std::io::{BufRead, BufReader, ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, ExitStatus, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::error::{DeoEncodingError, DirName, HandbrakeCommand, LogFile};
+use crate::journal::{Journal, JournalStatus};
+use crate::models::SessionId;
+use crate::profiles::ProfileConfigItem;
+use crate::hb_output_parser::EncoderState;
+use super::EncodeJob;
+use super::backend::ProgressParser;
+use super::disk_space::{self, SizeEstimator};
+use super::encode_logs::{self, EncodeLogs};
+use super::existing_output::{resolve_output, OutputResolution};
+use super::hooks::HookEvent;
+use super::interrupt::{self, Interrupt};
+use super::source_disposition::{self, dispose_completed, dispose_failed, DisposeAfter};
+use super::run_log::{FileStatus, RunLog, RunLogRecord};
+use super::summary::RunSummary;
+use super::verify::verify_output;
+use super::options::EncodeOptions;
+use super::partial_output::{self, partial_path};
+
+/// How often a running encode checks whether the run has been cancelled
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// How a running encoder finished.
+#[derive(Debug, PartialEq)]
+enum EncoderOutcome {
+  Exited(ExitStatus),
+  Cancelled,
+  TimedOut(String),
+}
+
+/// A single run of the encoder for a file, which may be retried with another profile.
+struct Attempt<'a> {
+  number: u32,
+  profile: &'a ProfileConfigItem,
+}
+
+/// State shared between all encoding workers.
+struct EncodeContext {
+  options: EncodeOptions,
+  queue: Mutex<VecDeque<(usize, EncodeJob)>>,
+  journal: Mutex<Journal>,
+  run_log: RunLog,
+  prompt_lock: Mutex<()>,
+  aborted: AtomicBool,
+  out_of_space: AtomicBool,
+  /// Set when a file was left for later as another run is encoding it
+  deferred: AtomicBool,
+  interrupt: Interrupt,
+  multi: MultiProgress,
+  completed_bar: ProgressBar,
+  error_bar: ProgressBar,
+  verify_error_bar: ProgressBar,
+  skipped_bar: ProgressBar,
+}
+
+/// Encode every unfinished file in the journal, recording the outcome of each file in the journal as it completes.
+pub fn encode(journal: Journal, options: EncodeOptions) -> Result<(), DeoEncodingError> {
   println!("encoding...");
-  let mut cmd = Command::new("handbrakecli");
 
   let multi = MultiProgress::new();
 
-  let bar_style =
-    ProgressStyle::with_template("pass:{msg} {prefix} [{wide_bar:.green}] {pos:>3}/{len:3} {eta}").unwrap();
+  let jobs: VecDeque<(usize, EncodeJob)> =
+    journal
+      .unfinished()
+      .into_iter()
+      .map(|index| (index, journal.entries()[index].job.clone()))
+      .collect();
 
-  let bar =
-    ProgressBar::new(100)
-    .with_style(bar_style)
-    .with_finish(indicatif::ProgressFinish::Abandon);
+  journal
+    .save()
+    .map_err(DeoEncodingError::CouldNotUpdateJournal)?;
 
-  let completed_bar_style =
-    ProgressStyle::with_template("completed:{pos:>3}/{len:3} [{wide_bar:.blue}] {eta}").unwrap();
+  remove_stale_partials(&journal, options.scratch_dir.as_deref());
 
-  let file_count =
-    selections
-      .iter()
-      .map(|sel| {
-        sel.session_to_encode_dir().file_count()
-      })
-      .sum();
+  if options.check_space {
+    check_space(&jobs, &options)?;
+  }
+
+  let file_count = jobs.len() as u64;
 
+  let completed_bar_style =
+    ProgressStyle::with_template("completed:{pos:>3}/{len:3} [{wide_bar:.blue}] {eta}").unwrap();
 
   let completed_bar =
     ProgressBar::new(file_count)
@@ -48,99 +108,775 @@ pub fn encode(selections: Vec<UserSelection>) -> Result<(), DeoEncodingError> {
       .with_style(error_bar_style)
       .with_finish(indicatif::ProgressFinish::Abandon);
 
-  multi.add(bar.clone());
+  let skipped_bar_style =
+    ProgressStyle::with_template("skipped:{pos:>3}/{len:3} [{wide_bar:.yellow}]").unwrap();
+
+  let skipped_bar =
+    ProgressBar::new(file_count)
+      .with_style(skipped_bar_style)
+      .with_finish(indicatif::ProgressFinish::Abandon);
+
+  let verify_error_bar_style =
+    ProgressStyle::with_template("unverified:{pos:>3}/{len:3} [{wide_bar:.magenta}]").unwrap();
+
+  // Verification failures are only worth a bar when verifying
+  let verify_error_bar =
+    if options.verify {
+      ProgressBar::new(file_count)
+        .with_style(verify_error_bar_style)
+        .with_finish(indicatif::ProgressFinish::Abandon)
+    } else {
+      ProgressBar::hidden()
+    };
+
   multi.add(completed_bar.clone());
   multi.add(error_bar.clone());
+  if options.verify {
+    multi.add(verify_error_bar.clone());
+  }
+  multi.add(skipped_bar.clone());
 
   completed_bar.set_position(0);
   error_bar.set_position(0);
+  verify_error_bar.set_position(0);
+  skipped_bar.set_position(0);
 
-  let log_file_path = Path::new("deo.log");
-  if !selections.is_empty() && log_file_path.exists() {
-    std::fs::remove_file(log_file_path)
-      .map_err(|e| DeoEncodingError::CouldNotRemoveLogFile(LogFile::new(log_file_path), e.to_string()))?
-  }
-
-  let mut log_file =
-    OpenOptions::new()
-      .create_new(true)
-      .append(true)
-      .open(log_file_path)
-      .map_err(|e| DeoEncodingError::CouldNotOpenLogFile(LogFile::new(log_file_path), e.to_string()))?;
-
-  let handbrake_infos: Vec<HandbrakeInfo> = selections.into_iter().map(|us| us.into()).collect();
-  for handbrake_info in handbrake_infos {
-    for input in handbrake_info.input_files {
-      bar.set_message("0");
-      let input_file = &input.mkv_path;
-      let output_file = handbrake_info.encode_dir_path.join(input.mp4_file);
-      bar.set_prefix(input.mkv_file);
-
-      let profile = handbrake_info.profile.clone();
-
-      let mut handbrake =
-        cmd
-          .arg("--preset-import-file")
-          .arg(profile.full_path())
-          .arg("-Z")
-          .arg(profile.preset_name())
-          .arg("--json")
-          .arg("-i")
-          .arg(input_file)
-          .arg("-o")
-          .arg(&output_file)
-          .stdout(Stdio::piped())
-          .stderr(Stdio::null())
-          .spawn()
-          .map_err(|e| {
-            let cmd_string =
-              format!(
-                "handbrakecli --json --preset-import-file {} -Z {} -i {} -o {}",
-                profile.full_path(),
-                profile.preset_name(),
-                input_file.to_string_lossy(),
-                output_file.to_string_lossy()
-              );
-
-            DeoEncodingError::FailedToSpawnHandbrake(HandbrakeCommand::new(cmd_string), e.to_string())
-          })?;
-
-      use std::io::{BufReader, BufRead};
-      let out = handbrake.stdout.take().unwrap();
-      let stdout_reader = BufReader::new(out);
-      let lines = stdout_reader.lines();
-
-      for line in lines {
-        let unwrapped_line = line.unwrap();
-        match parse(&unwrapped_line) {
-          Output::Progress(progress) => {
-            bar.set_position(progress as u64)
-          },
-          Output::Pass(pass) => {
-            bar.set_message(pass.to_string())
-          },
-          Output::Ignore => (),
-          Output::Done(error_code) => {
-            eprint!("Could not parse handbrake output line: {}, error_code: {}", &unwrapped_line, error_code);
-            bar.finish_and_clear()
-          }
-        }
+  let interrupt = interrupt::install()?;
+  let run_log = RunLog::create(&options.log_dir)?;
+  println!("logging run {} to {}", run_log.run_id(), run_log.dir().to_string_lossy());
+
+  // Never start more workers than there are files to encode
+  let worker_count = options.jobs.min(jobs.len()).max(1);
+
+  let context =
+    EncodeContext {
+      options,
+      queue: Mutex::new(jobs),
+      journal: Mutex::new(journal),
+      run_log,
+      prompt_lock: Mutex::new(()),
+      aborted: AtomicBool::new(false),
+      out_of_space: AtomicBool::new(false),
+      deferred: AtomicBool::new(false),
+      interrupt,
+      multi,
+      completed_bar,
+      error_bar,
+      verify_error_bar,
+      skipped_bar,
+    };
+
+  let run_env = run_env(&context, file_count);
+  run_run_hook(&context, HookEvent::RunStart, run_env.clone());
+
+  let result = thread::scope(|scope| {
+    let workers: Vec<_> =
+      (0..worker_count)
+        .map(|_| {
+          let bar = context.multi.insert_before(&context.completed_bar, new_job_bar());
+          let context = &context;
+          scope.spawn(move || run_worker(context, bar))
+        })
+        .collect();
+
+    workers
+      .into_iter()
+      .map(|worker| worker.join().expect("Encoding worker panicked"))
+      .collect::<Result<Vec<()>, DeoEncodingError>>()
+  });
+
+  let failed_count = context.error_bar.position() + context.verify_error_bar.position();
+  let end_env =
+    [
+      ("DEO_FAILED", failed_count.to_string()),
+      ("DEO_CANCELLED", context.interrupt.is_requested().to_string()),
+    ];
+  run_run_hook(&context, HookEvent::RunEnd, run_env.into_iter().chain(end_env).collect());
+
+  // The summary covers whatever was encoded, even when a worker failed
+  let summary = RunSummary::from_records(context.run_log.run_id(), &context.run_log.records());
+  if !summary.is_empty() {
+    println!("\n{}", summary.table());
+  }
+
+  let report_result =
+    context
+      .options
+      .report
+      .as_ref()
+      .map_or(Ok(()), |report| summary.write_report(report));
+
+  result?;
+  report_result?;
+
+  if context.interrupt.is_requested() {
+    println!("encoding was cancelled, use `deo resume` to encode the remaining files")
+  } else if context.out_of_space.load(Ordering::SeqCst) {
+    println!("encoding stopped as free space ran low, free some space and use `deo resume` to encode the remaining files")
+  } else if context.deferred.load(Ordering::SeqCst) {
+    println!("some files were being encoded by another run, use `deo resume` to encode them once it has finished")
+  }
+
+  Ok(())
+}
+
+/// Remove the partial outputs that earlier runs left behind in the output and scratch directories of the journal.
+fn remove_stale_partials(journal: &Journal, scratch_dir: Option<&Path>) {
+  let mut dirs: Vec<PathBuf> = vec![];
+  for entry in journal.entries() {
+    let job_dirs =
+      std::iter::once(entry.job.encode_dir_path.clone())
+        .chain(scratch_dir.map(|scratch_dir| scratch_dir.join(entry.job.session_id.id())));
+
+    for dir in job_dirs {
+      if !dirs.contains(&dir) {
+        dirs.push(dir)
       }
+    }
+  }
 
-      let exit_status = handbrake.wait().expect("Could not get output");
+  for (partial_file, removed) in partial_output::remove_stale(&dirs) {
+    match removed {
+      Ok(_) => println!("removed the partial output of an earlier run: {}", partial_file.to_string_lossy()),
+      Err(e) => eprintln!("{}", style(format!("could not remove the partial output of an earlier run: {} due to: {}", partial_file.to_string_lossy(), e)).yellow()),
+    }
+  }
+}
+
+fn new_job_bar() -> ProgressBar {
+  let bar_style =
+    ProgressStyle::with_template("pass:{msg} {prefix} [{wide_bar:.green}] {pos:>3}/{len:3}").unwrap();
 
-      let code = exit_status.code().expect("Could not get exit code");
-      if code != 0 {
-        error_bar.inc(1);
-        log_file.write_all(&format!("{} ❌\n", input_file.to_string_lossy()).into_bytes()).unwrap();
-      } else {
-        log_file.write_all(&format!("{} ✅\n", input_file.to_string_lossy()).into_bytes()).unwrap();
+  ProgressBar::new(100)
+    .with_style(bar_style)
+    .with_finish(indicatif::ProgressFinish::Abandon)
+}
+
+/// Take jobs off the shared queue and encode them one at a time, until the queue is empty or another worker has failed.
+fn run_worker(context: &EncodeContext, bar: ProgressBar) -> Result<(), DeoEncodingError> {
+  while let Some((index, job)) = next_job(context) {
+    encode_job(context, &bar, index, job)
+      .inspect_err(|_| context.aborted.store(true, Ordering::SeqCst))?
+  }
+
+  bar.finish_and_clear();
+  Ok(())
+}
+
+fn next_job(context: &EncodeContext) -> Option<(usize, EncodeJob)> {
+  if context.aborted.load(Ordering::SeqCst) || context.interrupt.is_requested() || context.out_of_space.load(Ordering::SeqCst) {
+    return None
+  }
+
+  let mut queue = context.queue.lock().unwrap();
+  let (index, job) = queue.pop_front()?;
+
+  match low_space_reason(context, &job) {
+    Some(reason) => {
+      // The job stays unfinished in the journal, so it's encoded by `deo resume`
+      queue.push_front((index, job));
+      if !context.out_of_space.swap(true, Ordering::SeqCst) {
+        print_above_bars(context, style(format!("not starting any more files: {}", reason)).red().to_string())
       }
+      None
+    },
+    None => Some((index, job)),
+  }
+}
+
+/// Why a job shouldn't be started, if the filesystem it's encoded to has less than the minimum free space.
+fn low_space_reason(context: &EncodeContext, job: &EncodeJob) -> Option<String> {
+  let min_free_space = context.options.min_free_space;
+  let dirs =
+    std::iter::once(job.encode_dir_path.clone())
+      .chain(context.options.scratch_dir.clone());
+
+  dirs
+    .filter_map(|dir| disk_space::available_space(&dir).map(|available| (dir, available)))
+    .find(|(_, available)| *available < min_free_space)
+    .map(|(dir, available)| format!("only {} is free for {}, which is less than the minimum of {}", HumanBytes(available), dir.to_string_lossy(), HumanBytes(min_free_space)))
+}
+
+/// Make sure the estimated size of every output fits on the filesystem it's encoded to, with the minimum free space to
+/// spare.
+fn check_space(jobs: &VecDeque<(usize, EncodeJob)>, options: &EncodeOptions) -> Result<(), DeoEncodingError> {
+  let estimator = SizeEstimator::from_history(&options.log_dir, options.output_size_factor);
+  let jobs: Vec<&EncodeJob> = jobs.iter().map(|(_, job)| job).collect();
+
+  let short_filesystems: Vec<String> =
+    disk_space::required_space(&jobs, &estimator, options.scratch_dir.as_deref(), options.jobs)
+      .into_iter()
+      .filter(|space| !space.is_enough(options.min_free_space))
+      .map(|space| space.to_string())
+      .collect();
+
+  if short_filesystems.is_empty() {
+    Ok(())
+  } else {
+    Err(DeoEncodingError::NotEnoughFreeSpace(HumanBytes(options.min_free_space).to_string(), short_filesystems))
+  }
+}
+
+fn encode_job(context: &EncodeContext, bar: &ProgressBar, index: usize, job: EncodeJob) -> Result<(), DeoEncodingError> {
+  bar.set_message("0");
+  bar.set_position(0);
+  let input = &job.input;
+  let start_time = Local::now();
+
+  let resolution = {
+    // Only one worker can ask the user a question at a time
+    let _prompt_guard = context.prompt_lock.lock().unwrap();
+    resolve_output(job.encode_dir_path.join(&job.output_file), context.options.on_existing, &context.multi)
+  };
+
+  let output_file = match resolution {
+    OutputResolution::Encode(output_file) => output_file,
+    OutputResolution::Skip => {
+      context.skipped_bar.inc(1);
+      context.completed_bar.inc(1);
+      let output_file = job.encode_dir_path.join(&job.output_file);
+      context.run_log.write(&RunLogRecord::new(context.run_log.run_id(), &job, &output_file, FileStatus::Skipped, start_time))?;
+      return skip_job(context, index, &job)
+    }
+  };
+  bar.set_prefix(input.mkv_file.clone());
+
+  let partial_file = partial_path(&output_file, &job.session_id, context.options.scratch_dir.as_deref());
+  if let Some(partial_dir) = partial_file.parent().filter(|_| context.options.scratch_dir.is_some()) {
+    std::fs::create_dir_all(partial_dir)
+      .map_err(|e| DeoEncodingError::CouldNotCreateScratchDir(DirName::new(partial_dir), e.to_string()))?;
+  }
+
+  // Held while encoding, so that other runs never remove the partial output as stale
+  let mut _partial_lock =
+    match lock_partial_output(context, &job, &partial_file) {
+      PartialLock::Held(partial_lock) => partial_lock,
+      PartialLock::HeldByAnotherRun => return Ok(()),
+    };
 
-      completed_bar.inc(1);
-      log_file.flush().unwrap();
+  let pre_hook_failure = context.options.hooks.failure(HookEvent::FilePre, &file_env(context, &job, &job.profile, &output_file), context.run_log.dir());
+
+  if let Some(reason) = pre_hook_failure {
+    context.skipped_bar.inc(1);
+    context.completed_bar.inc(1);
+    print_above_bars(context, style(format!("skipping {}: {}", input.mkv_file, reason)).yellow().to_string());
+    let record = RunLogRecord { reason: Some(reason), ..RunLogRecord::new(context.run_log.run_id(), &job, &output_file, FileStatus::Skipped, start_time) };
+    context.run_log.write(&record)?;
+    return skip_job(context, index, &job)
+  }
+
+  update_journal(context, index, JournalStatus::Encoding)?;
+
+  let attempt_profiles = job.attempt_profiles(context.options.retries);
+  let attempt_count = attempt_profiles.len();
+  let mut status = FileStatus::Failed;
+  let mut exit_code = None;
+  let mut attempts_made = 0;
+  let mut last_profile = &job.profile;
+
+  for (attempt, profile) in (1..).zip(attempt_profiles) {
+    attempts_made = attempt;
+    last_profile = profile;
+
+    if attempt > 1 {
+      let message = format!("retrying {} with {} (attempt {}/{})", input.mkv_file, profile, attempt, attempt_count);
+      print_above_bars(context, style(message).yellow().to_string());
+
+      // The failed attempt deleted the locked partial output, so lock the one this attempt writes
+      _partial_lock =
+        match lock_partial_output(context, &job, &partial_file) {
+          PartialLock::Held(partial_lock) => partial_lock,
+          PartialLock::HeldByAnotherRun => return update_journal(context, index, JournalStatus::Pending),
+        };
+    }
+
+    (status, exit_code) = encode_attempt(context, bar, &job, Attempt { number: attempt, profile }, &output_file, &partial_file)?;
+
+    // Ctrl-C stops the whole run, so don't start another attempt
+    if !matches!(status, FileStatus::Failed | FileStatus::VerificationFailed) || context.interrupt.is_requested() {
+      break
+    }
+  }
+
+  match status {
+    FileStatus::Failed => context.error_bar.inc(1),
+    FileStatus::VerificationFailed => context.verify_error_bar.inc(1),
+    _ => (),
+  }
+
+  // A cancelled file is encoded again on resume, so it hasn't been completed
+  if status != FileStatus::Cancelled {
+    context.completed_bar.inc(1);
+  }
+
+  let successful_session = finish_journal_entry(context, index, &job.session_id, journal_status(status))?;
+
+  if status != FileStatus::Cancelled {
+    let post_env =
+      [
+        ("DEO_STATUS", status_name(status)),
+        ("DEO_EXIT_CODE", exit_code.map(|code| code.to_string()).unwrap_or_default()),
+      ];
+
+    let env: Vec<(&str, String)> = file_env(context, &job, last_profile, &output_file).into_iter().chain(post_env).collect();
+    if let Err(error) = context.options.hooks.run(HookEvent::FilePost, &env, context.run_log.dir()) {
+      print_above_bars(context, style(error).yellow().to_string())
     }
   }
 
+  dispose_sources(context, &job, status, attempts_made, successful_session);
   Ok(())
 }
+
+/// Record a file that wasn't encoded. It may be the last file of its session to finish, in which case the sources of
+/// the session are disposed of.
+fn skip_job(context: &EncodeContext, index: usize, job: &EncodeJob) -> Result<(), DeoEncodingError> {
+  let successful_session = finish_journal_entry(context, index, &job.session_id, JournalStatus::Skipped)?;
+  dispose_sources(context, job, FileStatus::Skipped, 0, successful_session);
+  Ok(())
+}
+
+/// Record how a file finished in the journal. When sources are disposed of after each session, returns the completed
+/// jobs of its session that are still to be disposed of once this was the last file of the session to finish, and
+/// none of them failed.
+fn finish_journal_entry(context: &EncodeContext, index: usize, session_id: &SessionId, status: JournalStatus) -> Result<Option<Vec<EncodeJob>>, DeoEncodingError> {
+  let mut journal = context.journal.lock().unwrap();
+  journal
+    .update(index, status)
+    .map_err(DeoEncodingError::CouldNotUpdateJournal)?;
+
+  if context.options.source_disposition.dispose_after != DisposeAfter::Session {
+    return Ok(None)
+  }
+
+  // Checked while holding the journal, so only the worker that finishes the session sees it as successful
+  let successful_session = journal.dispose_successful_session(session_id);
+  if successful_session.is_some() {
+    journal
+      .save()
+      .map_err(DeoEncodingError::CouldNotUpdateJournal)?;
+  }
+
+  Ok(successful_session)
+}
+
+/// Environment variables that tell a run hook about the run.
+fn run_env(context: &EncodeContext, file_count: u64) -> Vec<(&'static str, String)> {
+  vec![
+    ("DEO_RUN_ID", context.run_log.run_id().to_owned()),
+    ("DEO_RUN_DIR", context.run_log.dir().to_string_lossy().to_string()),
+    ("DEO_FILE_COUNT", file_count.to_string()),
+  ]
+}
+
+/// Environment variables that tell a file hook about the file, and the profile it is encoded with.
+fn file_env(context: &EncodeContext, job: &EncodeJob, profile: &ProfileConfigItem, output_file: &Path) -> Vec<(&'static str, String)> {
+  vec![
+    ("DEO_RUN_ID", context.run_log.run_id().to_owned()),
+    ("DEO_SESSION", job.session_id.id().to_owned()),
+    ("DEO_INPUT", job.input.mkv_path.to_string_lossy().to_string()),
+    ("DEO_OUTPUT", output_file.to_string_lossy().to_string()),
+    ("DEO_PROFILE", profile.to_string()),
+  ]
+}
+
+/// Run a hook for the whole run. A failing run hook is reported, but doesn't stop the run.
+fn run_run_hook(context: &EncodeContext, event: HookEvent, env: Vec<(&str, String)>) {
+  if let Some(failure) = context.options.hooks.failure(event, &env, context.run_log.dir()) {
+    print_above_bars(context, style(failure).yellow().to_string())
+  }
+}
+
+/// The name of a status as it appears in the run log.
+fn status_name(status: FileStatus) -> String {
+  serde_json::to_value(status)
+    .ok()
+    .and_then(|value| value.as_str().map(str::to_owned))
+    .unwrap_or_default()
+}
+
+/// Delete, archive or move the sources of finished files as configured. Sources that failed verification or were
+/// cancelled are never touched. Problems are reported but never fail the run, as the encodes themselves are fine.
+fn dispose_sources(context: &EncodeContext, job: &EncodeJob, status: FileStatus, attempts_made: u32, successful_session: Option<Vec<EncodeJob>>) {
+  let disposition = &context.options.source_disposition;
+
+  let results: Vec<Result<Option<String>, String>> =
+    match (status, disposition.dispose_after, &disposition.failed_dir) {
+      (FileStatus::Completed, DisposeAfter::File, _) => vec![dispose_completed(&disposition.on_success, job)],
+      (FileStatus::Failed, _, Some(failed_dir)) => {
+        let logs: Vec<PathBuf> =
+          (1..=attempts_made)
+            .flat_map(|attempt| {
+              let encode_logs = EncodeLogs::new(context.run_log.dir(), job, attempt, context.options.keep_json_output);
+              std::iter::once(encode_logs.stderr_log).chain(encode_logs.stdout_log)
+            })
+            .collect();
+
+        vec![dispose_failed(failed_dir, job, &logs).map(Some)]
+      },
+      _ => vec![],
+    };
+
+  let session_results: Vec<Result<Option<String>, String>> =
+    match (disposition.dispose_after, successful_session) {
+      (DisposeAfter::Session, Some(session_jobs)) => {
+        session_jobs
+          .iter()
+          .map(|session_job| dispose_completed(&disposition.on_success, session_job))
+          .collect()
+      },
+      _ => vec![],
+    };
+
+  for result in results.into_iter().chain(session_results) {
+    let logged = match result {
+      Ok(Some(action)) => source_disposition::log_action(context.run_log.dir(), &action),
+      Ok(None) => Ok(()),
+      Err(error) => Err(error),
+    };
+
+    if let Err(error) = logged {
+      print_above_bars(context, style(format!("source of {} was not disposed of: {}", job.input.mkv_file, error)).yellow().to_string())
+    }
+  }
+}
+
+/// Run the encoder once for a job and record how it went in the run log.
+fn encode_attempt(context: &EncodeContext, bar: &ProgressBar, job: &EncodeJob, attempt: Attempt, output_file: &Path, partial_file: &Path) -> Result<(FileStatus, Option<i32>), DeoEncodingError> {
+  bar.set_message("0");
+  bar.set_position(0);
+  let input = &job.input;
+  let input_file = &input.mkv_path;
+  let start_time = Local::now();
+
+  let encoder = context.options.encoders.for_profile(attempt.profile);
+  let encode_command = encoder.encode_command(attempt.profile, input_file, partial_file);
+  let encode_logs = EncodeLogs::new(context.run_log.dir(), job, attempt.number, context.options.keep_json_output);
+
+  let stderr_log = create_log_file(&encode_logs.stderr_log)?;
+  let mut stdout_log =
+    encode_logs
+      .stdout_log
+      .as_deref()
+      .map(create_log_file)
+      .transpose()?;
+
+  let mut encoder_process =
+    encode_command
+      .command()
+      .stdout(Stdio::piped())
+      .stderr(stderr_log)
+      .spawn()
+      .map_err(|e| DeoEncodingError::FailedToSpawnEncoder(HandbrakeCommand::new(encode_command.to_string()), e.to_string()))?;
+
+  let progress_parser = encoder.progress_parser(input_file);
+  let outcome = watch_encoder(context, bar, &mut encoder_process, progress_parser, stdout_log.as_mut());
+  let outcome = interrupted_outcome(outcome, context.interrupt.is_requested());
+
+  let attempt_record = |status: FileStatus| {
+    RunLogRecord {
+      profile: attempt.profile.to_string(),
+      attempt: attempt.number,
+      log: Some(encode_logs.stderr_log.clone()),
+      ..RunLogRecord::new(context.run_log.run_id(), job, output_file, status, start_time)
+    }
+  };
+
+  let exit_status = match outcome {
+    EncoderOutcome::Exited(exit_status) => exit_status,
+    EncoderOutcome::Cancelled => {
+      let record = RunLogRecord { reason: Some("cancelled by the user".to_owned()), ..attempt_record(FileStatus::Cancelled) };
+      return abandon_attempt(context, record, partial_file).map(|status| (status, None))
+    },
+    EncoderOutcome::TimedOut(reason) => {
+      print_above_bars(context, style(format!("{} failed: {}", input.mkv_file, reason)).red().to_string());
+      let record = RunLogRecord { reason: Some(reason), ..attempt_record(FileStatus::Failed) };
+      return abandon_attempt(context, record, partial_file).map(|status| (status, None))
+    },
+  };
+
+  let (status, reason) = if !exit_status.success() {
+    show_failure(context, &input.mkv_file, &encode_logs);
+    (FileStatus::Failed, None)
+  } else if context.options.verify {
+    bar.set_message("verify");
+    match verify_output(encoder, input_file, partial_file, context.options.verify_tolerance) {
+      Ok(_) => (FileStatus::Completed, None),
+      Err(reason) => {
+        print_above_bars(context, style(format!("{} failed verification: {}", input.mkv_file, reason)).magenta().to_string());
+        (FileStatus::VerificationFailed, Some(reason))
+      }
+    }
+  } else {
+    (FileStatus::Completed, None)
+  };
+
+  // Only a finished and verified encode ever appears at its output
+  let (status, reason) =
+    if status == FileStatus::Completed {
+      match partial_output::move_into_place(partial_file, output_file) {
+        Ok(_) => (status, reason),
+        Err(e) => {
+          let reason = format!("could not move {} to its output due to: {}", partial_file.to_string_lossy(), e);
+          print_above_bars(context, style(format!("{} failed: {}", input.mkv_file, reason)).red().to_string());
+          (FileStatus::Failed, Some(reason))
+        },
+      }
+    } else {
+      // The partial output is of no use once the attempt has failed, and would otherwise be removed by the next run
+      let _ = std::fs::remove_file(partial_file);
+      (status, reason)
+    };
+
+  let record = attempt_record(status);
+  let record =
+    RunLogRecord {
+      exit_code: exit_status.code(),
+      reason,
+      // An output that exists after a failed attempt was encoded by an earlier run
+      output_size: record.output_size.filter(|_| status == FileStatus::Completed),
+      ..record
+    };
+
+  context.run_log.write(&record)?;
+  Ok((status, exit_status.code()))
+}
+
+fn journal_status(status: FileStatus) -> JournalStatus {
+  match status {
+    FileStatus::Completed => JournalStatus::Completed,
+    FileStatus::Failed => JournalStatus::Failed,
+    FileStatus::VerificationFailed => JournalStatus::VerificationFailed,
+    FileStatus::Skipped => JournalStatus::Skipped,
+    FileStatus::Cancelled => JournalStatus::Cancelled,
+  }
+}
+
+/// Show the progress of a running encoder until it exits, or until it is killed because the user cancelled the run or
+/// it timed out.
+fn watch_encoder(context: &EncodeContext, bar: &ProgressBar, encoder: &mut Child, mut progress_parser: Box<dyn ProgressParser>, mut stdout_log: Option<&mut File>) -> EncoderOutcome {
+  let (sender, receiver) = mpsc::channel();
+  let stdout = encoder.stdout.take().expect("encoder stdout is piped");
+
+  // Lines are read on their own thread so that a quiet encoder never stops us from noticing a cancelled run
+  let reader =
+    thread::spawn(move || {
+      for line in BufReader::new(stdout).lines().map_while(Result::ok) {
+        if sender.send(line).is_err() {
+          break
+        }
+      }
+    });
+
+  let started = Instant::now();
+  let mut last_change = started;
+  let mut last_marker = None;
+
+  let stopped =
+    loop {
+      if context.interrupt.is_requested() {
+        break Some(EncoderOutcome::Cancelled)
+      }
+
+      if let Some(reason) = timeout_reason(&context.options, started, last_change) {
+        break Some(EncoderOutcome::TimedOut(reason))
+      }
+
+      match receiver.recv_timeout(POLL_INTERVAL) {
+        Ok(line) => {
+          if let Some(log) = stdout_log.as_mut() {
+            // The raw output is only kept for troubleshooting, so don't fail the encode if it can't be written
+            let _ = writeln!(log, "{}", line);
+          }
+
+          if let Some(state) = progress_parser.progress(&line) {
+            let marker = progress_marker(&state);
+            if marker.is_some() && marker != last_marker {
+              last_marker = marker;
+              last_change = Instant::now();
+            }
+
+            show_progress(bar, state)
+          }
+        },
+        Err(RecvTimeoutError::Timeout) => (),
+        Err(RecvTimeoutError::Disconnected) => break None,
+      }
+    };
+
+  if stopped.is_some() {
+    // The encoder may have already exited on the same signal, in which case there is nothing to kill
+    let _ = encoder.kill();
+  }
+
+  let exit_status = encoder.wait().expect("Could not get output");
+
+  match stopped {
+    // Anything the encoder started may still hold its stdout open, so don't wait for the reader of a killed encoder
+    Some(outcome) => outcome,
+    None => {
+      let _ = reader.join();
+      EncoderOutcome::Exited(exit_status)
+    },
+  }
+}
+
+/// Ctrl-C also reaches the encoder, which may exit before the interrupt is noticed. Its exit is then a cancellation
+/// rather than a failure, so that the file is encoded again on resume.
+fn interrupted_outcome(outcome: EncoderOutcome, interrupted: bool) -> EncoderOutcome {
+  match outcome {
+    EncoderOutcome::Exited(_) if interrupted => EncoderOutcome::Cancelled,
+    outcome => outcome,
+  }
+}
+
+/// Why the encoder should be stopped, if it has run for longer than allowed or hasn't made any progress for too long.
+fn timeout_reason(options: &EncodeOptions, started: Instant, last_change: Instant) -> Option<String> {
+  let max_file_time = options.max_file_time.filter(|max_file_time| started.elapsed() >= *max_file_time);
+  let stall_timeout = options.stall_timeout.filter(|stall_timeout| last_change.elapsed() >= *stall_timeout);
+
+  match (max_file_time, stall_timeout) {
+    (Some(max_file_time), _) => Some(format!("timed out, the encode took longer than {}", minutes_text(max_file_time))),
+    (_, Some(stall_timeout)) => Some(format!("timed out, the encoder made no progress for {}", minutes_text(stall_timeout))),
+    (None, None) => None,
+  }
+}
+
+fn minutes_text(duration: Duration) -> String {
+  match duration.as_secs() / 60 {
+    1 => "1 minute".to_owned(),
+    minutes => format!("{} minutes", minutes),
+  }
+}
+
+/// What identifies progress in a state. An encoder can report the same progress repeatedly while it's stuck, so only a
+/// change in this counts as progress.
+fn progress_marker(state: &EncoderState) -> Option<(&'static str, u32, u64)> {
+  // Progress is compared at the resolution of the progress bar
+  let percent = |progress: f64| (progress * 100.0) as u64;
+
+  match state {
+    EncoderState::Scanning(scanning) => Some(("scan", scanning.title, percent(scanning.progress))),
+    EncoderState::Working(working) => Some(("work", working.pass, percent(working.progress))),
+    EncoderState::Muxing(muxing) => Some(("mux", 0, percent(muxing.progress))),
+    EncoderState::WorkDone(_) => Some(("done", 0, 100)),
+    EncoderState::Other(_) => None,
+  }
+}
+
+/// Remove the partial output of an attempt whose encoder was killed.
+fn abandon_attempt(context: &EncodeContext, record: RunLogRecord, partial_file: &Path) -> Result<FileStatus, DeoEncodingError> {
+  let reason = record.reason.clone().unwrap_or_default();
+  let record = RunLogRecord { output_size: None, ..record };
+
+  let record =
+    match std::fs::remove_file(partial_file) {
+      Ok(_) => RunLogRecord { reason: Some(format!("{}, the partial output was deleted", reason)), ..record },
+      Err(e) if e.kind() == ErrorKind::NotFound => record,
+      Err(e) => RunLogRecord { reason: Some(format!("{}, could not delete the partial output: {}", reason, e)), ..record },
+    };
+
+  context.run_log.write(&record)?;
+  Ok(record.status)
+}
+
+fn create_log_file(path: &Path) -> Result<File, DeoEncodingError> {
+  File::create(path)
+    .map_err(|e| DeoEncodingError::CouldNotOpenLogFile(LogFile::new(path), e.to_string()))
+}
+
+/// Print the end of the encoder log for a failed file above the progress bars.
+fn show_failure(context: &EncodeContext, mkv_file: &str, encode_logs: &EncodeLogs) {
+  let mut lines = vec![format!("{} failed, last lines of {}:", mkv_file, encode_logs.stderr_log.to_string_lossy())];
+  lines.extend(
+    encode_logs::tail(&encode_logs.stderr_log, 10)
+      .into_iter()
+      .map(|line| format!("  {}", line))
+  );
+
+  print_above_bars(context, style(lines.join("\n")).red().to_string())
+}
+
+fn print_above_bars(context: &EncodeContext, message: String) {
+  // Progress bars are hidden when we're not attached to a terminal, which also hides anything printed through them
+  if context.multi.is_hidden() {
+    eprintln!("{}", message)
+  } else {
+    // Printing is best effort. It should never fail an encode
+    let _ = context.multi.println(message);
+  }
+}
+
+fn show_progress(bar: &ProgressBar, state: EncoderState) {
+  match state {
+    EncoderState::Scanning(scanning) => {
+      bar.set_message("scan");
+      bar.set_position((scanning.progress * 100.0) as u64)
+    },
+    EncoderState::Working(working) => {
+      let eta = FormattedDuration(Duration::from_secs(working.eta_seconds.max(0) as u64));
+      bar.set_message(format!("{}/{} {:.1}fps eta:{}", working.pass, working.pass_count, working.rate, eta));
+      bar.set_position((working.progress * 100.0) as u64)
+    },
+    EncoderState::Muxing(muxing) => {
+      bar.set_message("mux");
+      bar.set_position((muxing.progress * 100.0) as u64)
+    },
+    EncoderState::WorkDone(_) => bar.set_position(100),
+    EncoderState::Other(_) => (),
+  }
+}
+
+enum PartialLock {
+  /// None when the filesystem doesn't support locks, the encode then goes ahead without one
+  Held(Option<File>),
+  HeldByAnotherRun,
+}
+
+/// A file whose partial output is locked by another run is left unfinished in the journal, so that `deo resume`
+/// encodes it once the other run has finished.
+fn lock_partial_output(context: &EncodeContext, job: &EncodeJob, partial_file: &Path) -> PartialLock {
+  match partial_output::lock(partial_file) {
+    Ok(Some(partial_lock)) => PartialLock::Held(Some(partial_lock)),
+    Ok(None) => {
+      context.deferred.store(true, Ordering::SeqCst);
+      print_above_bars(context, style(format!("deferring {}: it is being encoded by another run", job.input.mkv_file)).yellow().to_string());
+      PartialLock::HeldByAnotherRun
+    },
+    Err(_) => PartialLock::Held(None),
+  }
+}
+
+fn update_journal(context: &EncodeContext, index: usize, status: JournalStatus) -> Result<(), DeoEncodingError> {
+  context
+    .journal
+    .lock()
+    .unwrap()
+    .update(index, status)
+    .map_err(DeoEncodingError::CouldNotUpdateJournal)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use std::os::unix::process::ExitStatusExt;
+
+    #[test]
+    fn an_encoder_that_exits_on_ctrl_c_is_cancelled() {
+      // Killed by SIGINT, and exited with an error after handling it
+      assert_eq!(interrupted_outcome(EncoderOutcome::Exited(ExitStatus::from_raw(2)), true), EncoderOutcome::Cancelled);
+      assert_eq!(interrupted_outcome(EncoderOutcome::Exited(ExitStatus::from_raw(1 << 8)), true), EncoderOutcome::Cancelled);
+    }
+
+    #[test]
+    fn an_encoder_that_exits_without_ctrl_c_keeps_its_exit_status() {
+      assert_eq!(interrupted_outcome(EncoderOutcome::Exited(ExitStatus::from_raw(1 << 8)), false), EncoderOutcome::Exited(ExitStatus::from_raw(1 << 8)));
+      assert_eq!(interrupted_outcome(EncoderOutcome::Exited(ExitStatus::from_raw(0)), false), EncoderOutcome::Exited(ExitStatus::from_raw(0)));
+    }
+
+    #[test]
+    fn a_timed_out_encoder_stays_timed_out() {
+      assert_eq!(interrupted_outcome(EncoderOutcome::TimedOut("stalled".to_owned()), true), EncoderOutcome::TimedOut("stalled".to_owned()));
+    }
+}
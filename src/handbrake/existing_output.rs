@@ -0,0 +1,115 @@
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use clap::ValueEnum;
+use dialoguer::{theme::ColorfulTheme, Select};
+use indicatif::MultiProgress;
+
+/// What to do when the output file of an encode already exists.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum OnExisting {
+  /// Don't encode the file again
+  Skip,
+  /// Encode the file again, replacing the existing output
+  Overwrite,
+  /// Encode the file to a new name alongside the existing output
+  Rename,
+  /// Ask what to do for each existing output
+  Ask,
+}
+
+pub enum OutputResolution {
+  Encode(PathBuf),
+  Skip,
+}
+
+#[derive(Clone, Copy)]
+enum ExistingChoice {
+  Skip,
+  Overwrite,
+  Rename,
+}
+
+impl fmt::Display for ExistingChoice {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let option = match self {
+      ExistingChoice::Skip => "Skip",
+      ExistingChoice::Overwrite => "Overwrite",
+      ExistingChoice::Rename => "Rename",
+    };
+
+    write!(f, "{}", option)
+  }
+}
+
+/// Decide where to encode to, given the desired output file and the policy for existing outputs.
+///
+/// When the policy is `Ask`, the progress bars in `multi` are hidden while the user is asked.
+pub fn resolve_output(output_file: PathBuf, on_existing: OnExisting, multi: &MultiProgress) -> OutputResolution {
+  if !output_file.exists() {
+    return OutputResolution::Encode(output_file)
+  }
+
+  let choice = match on_existing {
+    OnExisting::Skip => ExistingChoice::Skip,
+    OnExisting::Overwrite => ExistingChoice::Overwrite,
+    OnExisting::Rename => ExistingChoice::Rename,
+    OnExisting::Ask => ask_for_choice(&output_file, multi),
+  };
+
+  match choice {
+    ExistingChoice::Skip => OutputResolution::Skip,
+    ExistingChoice::Overwrite => OutputResolution::Encode(output_file),
+    ExistingChoice::Rename => OutputResolution::Encode(next_available_name(&output_file)),
+  }
+}
+
+fn ask_for_choice(output_file: &Path, multi: &MultiProgress) -> ExistingChoice {
+  let options = [ExistingChoice::Skip, ExistingChoice::Overwrite, ExistingChoice::Rename];
+  let question = format!("{} already exists:", output_file.to_string_lossy());
+
+  let answer = multi.suspend(|| {
+    Select::with_theme(&ColorfulTheme::default())
+      .with_prompt(&question)
+      .default(0)
+      .items(&options)
+      .interact()
+      .ok()
+  });
+
+  // If we can't get an answer, don't destroy the existing output
+  answer
+    .and_then(|index| options.get(index).copied())
+    .unwrap_or(ExistingChoice::Skip)
+}
+
+/// Find a name that doesn't exist yet by adding a counter to the file stem: `name (1).mp4`, `name (2).mp4` etc.
+fn next_available_name(output_file: &Path) -> PathBuf {
+  let stem = output_file.file_stem().map(|s| s.to_string_lossy().to_string()).unwrap_or_default();
+  let extension = output_file.extension().map(|e| format!(".{}", e.to_string_lossy())).unwrap_or_default();
+
+  (1..)
+    .map(|counter| output_file.with_file_name(format!("{stem} ({counter}){extension}")))
+    .find(|candidate| !candidate.exists())
+    .expect("Could not find an available file name")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn next_available_name_adds_a_counter() {
+      let output_file = Path::new("/does/not/exist/S01E01 - Exodus.mp4");
+      assert_eq!(next_available_name(output_file), PathBuf::from("/does/not/exist/S01E01 - Exodus (1).mp4"));
+    }
+
+    #[test]
+    fn missing_output_is_always_encoded() {
+      let output_file = PathBuf::from("/does/not/exist/S01E01 - Exodus.mp4");
+      let resolution = resolve_output(output_file.clone(), OnExisting::Ask, &MultiProgress::new());
+
+      assert!(matches!(resolution, OutputResolution::Encode(path) if path == output_file));
+    }
+}
@@ -0,0 +1,168 @@
+use std::fmt;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::Path;
+use std::process::Command;
+
+use chrono::Local;
+use serde::Deserialize;
+
+/// Shell commands that are run around a run and around each file. Each command is run with `sh -c`, and is told what
+/// is happening through `DEO_` environment variables.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Hooks {
+  /// Run before the first file is encoded
+  pub run_start: Option<String>,
+  /// Run before each file is encoded. The file is skipped when this exits with a non-zero code
+  pub file_pre: Option<String>,
+  /// Run after each file has been encoded, whether it succeeded or not
+  pub file_post: Option<String>,
+  /// Run after the last file has been encoded
+  pub run_end: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum HookEvent {
+  RunStart,
+  FilePre,
+  FilePost,
+  RunEnd,
+}
+
+impl fmt::Display for HookEvent {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let name = match self {
+      HookEvent::RunStart => "run_start",
+      HookEvent::FilePre => "file_pre",
+      HookEvent::FilePost => "file_post",
+      HookEvent::RunEnd => "run_end",
+    };
+
+    write!(f, "{}", name)
+  }
+}
+
+impl Hooks {
+  fn script(&self, event: HookEvent) -> Option<&str> {
+    let script = match event {
+      HookEvent::RunStart => &self.run_start,
+      HookEvent::FilePre => &self.file_pre,
+      HookEvent::FilePost => &self.file_post,
+      HookEvent::RunEnd => &self.run_end,
+    };
+
+    script.as_deref()
+  }
+
+  /// Run the hook for an event, if there is one, appending its output to `hooks.log` in the run directory. Returns the
+  /// exit code of the hook, which is `None` when there is no hook for the event.
+  pub fn run(&self, event: HookEvent, env: &[(&str, String)], run_dir: &Path) -> Result<Option<i32>, String> {
+    let Some(script) = self.script(event) else {
+      return Ok(None)
+    };
+
+    let output =
+      Command::new("sh")
+        .arg("-c")
+        .arg(script)
+        .envs(env.iter().map(|(name, value)| (name, value)))
+        .output()
+        .map_err(|e| format!("could not run the {} hook: {}", event, e))?;
+
+    // A hook killed by a signal has no exit code, which is treated like any other failure
+    let exit_code = output.status.code().unwrap_or(-1);
+
+    let mut entry = format!("[{}] {}: {}\n", Local::now().format("%Y-%m-%d %H:%M:%S"), event, script);
+    entry.push_str(&String::from_utf8_lossy(&output.stdout));
+    entry.push_str(&String::from_utf8_lossy(&output.stderr));
+    entry.push_str(&format!("[{}] {} exited with: {}\n", Local::now().format("%Y-%m-%d %H:%M:%S"), event, exit_code));
+
+    let hooks_log = run_dir.join("hooks.log");
+
+    // The whole entry is written at once, so the output of hooks run by different workers never interleaves
+    OpenOptions::new()
+      .create(true)
+      .append(true)
+      .open(&hooks_log)
+      .and_then(|mut file| file.write_all(entry.as_bytes()))
+      .map_err(|e| format!("could not write to {}: {}", hooks_log.to_string_lossy(), e))?;
+
+    Ok(Some(exit_code))
+  }
+
+  /// Run the hook for an event, and return why it failed if it exited with a non-zero code or couldn't be run. A
+  /// failing file_pre hook skips its file.
+  pub fn failure(&self, event: HookEvent, env: &[(&str, String)], run_dir: &Path) -> Option<String> {
+    match self.run(event, env, run_dir) {
+      Ok(None) | Ok(Some(0)) => None,
+      Ok(Some(exit_code)) => Some(format!("the {} hook exited with: {}", event, exit_code)),
+      Err(error) => Some(error),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use tempfile::TempDir;
+
+    fn hooks_log(run_dir: &Path) -> String {
+      std::fs::read_to_string(run_dir.join("hooks.log")).unwrap()
+    }
+
+    fn file_env() -> Vec<(&'static str, String)> {
+      vec![
+        ("DEO_SESSION", "session1".to_owned()),
+        ("DEO_INPUT", "/Rips/session1/renames/S01E01 - Exodus.mkv".to_owned()),
+      ]
+    }
+
+    #[test]
+    fn tells_the_hook_about_the_file() {
+      let run_dir = TempDir::new().unwrap();
+      let hooks = Hooks { file_post: Some(r#"echo "$DEO_SESSION: $DEO_INPUT""#.to_owned()), ..Hooks::default() };
+
+      assert_eq!(hooks.run(HookEvent::FilePost, &file_env(), run_dir.path()), Ok(Some(0)));
+      assert!(hooks_log(run_dir.path()).contains("\nsession1: /Rips/session1/renames/S01E01 - Exodus.mkv\n"));
+    }
+
+    #[test]
+    fn skips_the_file_when_the_file_pre_hook_fails() {
+      let run_dir = TempDir::new().unwrap();
+      let failing_hooks = Hooks { file_pre: Some("exit 3".to_owned()), ..Hooks::default() };
+      let passing_hooks = Hooks { file_pre: Some("true".to_owned()), ..Hooks::default() };
+
+      assert_eq!(failing_hooks.failure(HookEvent::FilePre, &file_env(), run_dir.path()), Some("the file_pre hook exited with: 3".to_owned()));
+      assert_eq!(passing_hooks.failure(HookEvent::FilePre, &file_env(), run_dir.path()), None);
+      assert_eq!(Hooks::default().failure(HookEvent::FilePre, &file_env(), run_dir.path()), None);
+    }
+
+    #[test]
+    fn appends_the_output_of_each_hook_to_the_log() {
+      let run_dir = TempDir::new().unwrap();
+      let hooks = Hooks { run_start: Some("echo starting".to_owned()), run_end: Some("echo finished >&2; exit 1".to_owned()), ..Hooks::default() };
+
+      hooks.run(HookEvent::RunStart, &[], run_dir.path()).unwrap();
+      hooks.run(HookEvent::RunEnd, &[], run_dir.path()).unwrap();
+
+      let lines: Vec<String> =
+        hooks_log(run_dir.path())
+          .lines()
+          .map(|line| line.split_once("] ").map_or(line, |(_, entry)| entry).to_owned())
+          .collect();
+
+      assert_eq!(
+        lines,
+        vec![
+          "run_start: echo starting",
+          "starting",
+          "run_start exited with: 0",
+          "run_end: echo finished >&2; exit 1",
+          "finished",
+          "run_end exited with: 1",
+        ]
+      );
+    }
+}
@@ -0,0 +1,45 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, OnceLock};
+
+use crate::error::DeoEncodingError;
+
+/// Exit code of a process that was stopped by SIGINT
+const INTERRUPTED_EXIT_CODE: i32 = 130;
+
+/// Set once the user has pressed Ctrl-C, or deo has been sent SIGTERM.
+#[derive(Debug, Clone, Default)]
+pub struct Interrupt {
+  requested: Arc<AtomicBool>,
+}
+
+impl Interrupt {
+  pub fn is_requested(&self) -> bool {
+    self.requested.load(Ordering::SeqCst)
+  }
+}
+
+/// The handler can only be installed once, so later runs of the same process, such as in watch mode, share it
+static INSTALLED: OnceLock<Interrupt> = OnceLock::new();
+
+/// Catch SIGINT, SIGTERM and SIGHUP. The first signal asks the running encodes to stop and clean up after themselves,
+/// while a second signal quits straight away.
+pub fn install() -> Result<Interrupt, DeoEncodingError> {
+  if let Some(interrupt) = INSTALLED.get() {
+    return Ok(interrupt.clone())
+  }
+
+  let interrupt = Interrupt::default();
+  let requested = interrupt.requested.clone();
+
+  ctrlc::set_handler(move || {
+    if requested.swap(true, Ordering::SeqCst) {
+      eprintln!("\nforce quitting");
+      std::process::exit(INTERRUPTED_EXIT_CODE)
+    } else {
+      eprintln!("\ncancelling the running encodes, press Ctrl-C again to force quit");
+    }
+  })
+  .map_err(|e| DeoEncodingError::CouldNotInstallSignalHandler(e.to_string()))?;
+
+  Ok(INSTALLED.get_or_init(|| interrupt).clone())
+}
@@ -1,28 +1,184 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use serde::{Deserialize, Serialize};
 
-use crate::models::InputFile;
-use crate::profiles::ProfileConfigItem;
-use crate::user_selection::UserSelection;
+use crate::models::{InputFile, SessionId};
+use crate::profiles::{Container, ProfileConfigItem};
+use crate::user_selection::{SelectedFile, UserSelection};
 
 #[derive(Debug, Clone)]
 pub struct HandbrakeInfo {
+  pub session_id: SessionId,
   pub encode_dir_path: PathBuf,
+  /// Each file to encode with its own profile, as files in a session may need different profiles
+  pub input_files: Vec<EncodeInput>
+}
+
+/// A file to encode with its profile, and the name of its output in the encode directory.
+#[derive(Debug, Clone)]
+pub struct EncodeInput {
+  pub input: InputFile,
   pub profile: ProfileConfigItem,
-  pub input_files: Vec<InputFile>
+  pub output_file: String,
 }
 
+/// A single file to encode, taken from a HandbrakeInfo.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EncodeJob {
+  pub session_id: SessionId,
+  pub encode_dir_path: PathBuf,
+  pub profile: ProfileConfigItem,
+  /// Profile for the last attempt at encoding the file, when retrying it
+  #[serde(default)]
+  pub fallback_profile: Option<ProfileConfigItem>,
+  pub input: InputFile,
+  /// Name of the output in the encode directory
+  pub output_file: String,
+}
 
-impl From<UserSelection> for HandbrakeInfo {
-  fn from(user_selection: UserSelection) -> Self {
-      let input_files: Vec<InputFile> = user_selection.session_to_encode_dir().rename_files();
+impl HandbrakeInfo {
+  /// The output of each file is named after its rename, with the supplied extension or else the one for the
+  /// container of its profile.
+  pub fn new(user_selection: UserSelection, extension: Option<&str>) -> Self {
+    let input_files =
+      user_selection
+        .files()
+        .iter()
+        .map(|SelectedFile { input, profile }| {
+          EncodeInput {
+            input: input.clone(),
+            profile: profile.clone(),
+            output_file: output_file_name(&input.mkv_file, profile.container(), extension),
+          }
+        })
+        .collect();
 
-      let encode_dir_path = user_selection.encode_dir_path();
-      let profile = user_selection.profile().clone();
+    Self {
+      session_id: user_selection.session_id().clone(),
+      encode_dir_path: user_selection.encode_dir_path(),
+      input_files,
+    }
+  }
 
-      Self {
-        input_files,
-        profile,
-        encode_dir_path,
-      }
+  pub fn jobs(&self) -> Vec<EncodeJob> {
+    self
+      .input_files
+      .iter()
+      .map(|file| {
+        EncodeJob {
+          session_id: self.session_id.clone(),
+          encode_dir_path: self.encode_dir_path.clone(),
+          profile: file.profile.clone(),
+          fallback_profile: None,
+          input: file.input.clone(),
+          output_file: file.output_file.clone(),
+        }
+      })
+      .collect()
+  }
+}
+
+fn output_file_name(mkv_file: &str, container: Container, extension: Option<&str>) -> String {
+  let extension = extension.unwrap_or(container.extension());
+  Path::new(mkv_file).with_extension(extension).to_string_lossy().to_string()
+}
+
+impl EncodeJob {
+  /// The profile of each attempt at encoding the file. The fallback profile, if any, is used for the last attempt.
+  pub fn attempt_profiles(&self, retries: usize) -> Vec<&ProfileConfigItem> {
+    let mut profiles = vec![&self.profile; retries + 1];
+
+    if let Some(fallback_profile) = self.fallback_profile.as_ref().filter(|_| retries > 0) {
+      profiles[retries] = fallback_profile
+    }
+
+    profiles
   }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use crate::profiles::Backend;
+
+    fn profile(name: &str) -> ProfileConfigItem {
+      ProfileConfigItem::new(name, Backend::Handbrake, Container::Mp4)
+    }
+
+    fn job(fallback_profile: Option<&str>) -> EncodeJob {
+      EncodeJob {
+        session_id: SessionId::new("session1"),
+        encode_dir_path: PathBuf::from("/Encodes/ThunderCats {tvdb-70355}/Season 01"),
+        profile: profile("Fast 1080p"),
+        fallback_profile: fallback_profile.map(profile),
+        input: InputFile { mkv_file: "S01E01 - Exodus.mkv".to_owned(), mkv_path: PathBuf::from("/Rips/session1/renames/S01E01 - Exodus.mkv") },
+        output_file: "S01E01 - Exodus.mp4".to_owned(),
+      }
+    }
+
+    fn profile_names(profiles: Vec<&ProfileConfigItem>) -> Vec<String> {
+      profiles
+        .into_iter()
+        .map(|p| p.to_string())
+        .collect()
+    }
+
+    #[test]
+    fn attempts_every_retry_with_the_profile_without_a_fallback() {
+      assert_eq!(profile_names(job(None).attempt_profiles(0)), vec!["Fast 1080p"]);
+      assert_eq!(profile_names(job(None).attempt_profiles(2)), vec!["Fast 1080p", "Fast 1080p", "Fast 1080p"]);
+    }
+
+    #[test]
+    fn encodes_each_file_with_its_own_profile() {
+      let selected_file = |episode: &str, profile_name: &str| {
+        EncodeInput {
+          input: InputFile { mkv_file: format!("{episode}.mkv"), mkv_path: PathBuf::from(format!("/Rips/session1/renames/{episode}.mkv")) },
+          profile: profile(profile_name),
+          output_file: format!("{episode}.mp4"),
+        }
+      };
+
+      let handbrake_info =
+        HandbrakeInfo {
+          session_id: SessionId::new("session1"),
+          encode_dir_path: PathBuf::from("/Encodes/ThunderCats {tvdb-70355}/Season 01"),
+          input_files: vec![selected_file("S01E01 - Exodus", "HQ 1080p"), selected_file("S01E02 - The Unholy Alliance", "Fast 1080p")],
+        };
+
+      let jobs: Vec<(String, String)> =
+        handbrake_info
+          .jobs()
+          .into_iter()
+          .map(|job| (job.input.mkv_file, job.profile.to_string()))
+          .collect();
+
+      assert_eq!(
+        jobs,
+        vec![
+          ("S01E01 - Exodus.mkv".to_owned(), "HQ 1080p".to_owned()),
+          ("S01E02 - The Unholy Alliance.mkv".to_owned(), "Fast 1080p".to_owned()),
+        ]
+      )
+    }
+
+    #[test]
+    fn names_outputs_with_the_extension_of_the_container() {
+      assert_eq!(output_file_name("S01E01 - Exodus.mkv", Container::Mp4, None), "S01E01 - Exodus.mp4");
+      assert_eq!(output_file_name("S01E01 - Exodus.mkv", Container::Mkv, None), "S01E01 - Exodus.mkv");
+      assert_eq!(output_file_name("Mr. Robot.mkv", Container::Webm, None), "Mr. Robot.webm");
+    }
+
+    #[test]
+    fn names_outputs_with_the_supplied_extension() {
+      assert_eq!(output_file_name("S01E01 - Exodus.mkv", Container::Mp4, Some("m4v")), "S01E01 - Exodus.m4v");
+    }
+
+    #[test]
+    fn uses_the_fallback_profile_for_the_last_attempt() {
+      let job = job(Some("Fast 1080p Software"));
+
+      assert_eq!(profile_names(job.attempt_profiles(0)), vec!["Fast 1080p"]);
+      assert_eq!(profile_names(job.attempt_profiles(2)), vec!["Fast 1080p", "Fast 1080p", "Fast 1080p Software"]);
+    }
+}
@@ -0,0 +1,61 @@
+use std::path::PathBuf;
+use std::time::Duration;
+
+use super::backend::Encoders;
+use super::existing_output::OnExisting;
+use super::hooks::Hooks;
+use super::source_disposition::SourceDisposition;
+use super::summary::Report;
+
+/// Options that control how the selected files are encoded.
+#[derive(Debug, Clone)]
+pub struct EncodeOptions {
+  pub encoders: Encoders,
+
+  pub on_existing: OnExisting,
+
+  /// Maximum number of files to encode at the same time
+  pub jobs: usize,
+
+  /// Whether to keep the raw JSON that handbrake writes to stdout, alongside its stderr log
+  pub keep_json_output: bool,
+
+  /// Whether to check that each output exists, isn't empty and is as long as its source after encoding it
+  pub verify: bool,
+
+  /// How many seconds the duration of an output may differ from its source when verifying
+  pub verify_tolerance: u64,
+
+  /// How long handbrake may go without making progress before the file is failed
+  pub stall_timeout: Option<Duration>,
+
+  /// How long handbrake may spend on a single file before the file is failed
+  pub max_file_time: Option<Duration>,
+
+  /// How many times to retry a file that failed to encode or failed verification
+  pub retries: usize,
+
+  /// What happens to the sources of files once they have been encoded
+  pub source_disposition: SourceDisposition,
+
+  /// Commands to run around the run and around each file
+  pub hooks: Hooks,
+
+  /// Directory that holds the logs of every run
+  pub log_dir: PathBuf,
+
+  /// Directory to encode files in before they are moved to their output, instead of next to the output
+  pub scratch_dir: Option<PathBuf>,
+
+  /// Whether to check that the outputs are estimated to fit before encoding
+  pub check_space: bool,
+
+  /// Bytes to keep free on the filesystems that files are encoded to
+  pub min_free_space: u64,
+
+  /// Ratio of output to source size to expect from profiles that haven't encoded anything yet
+  pub output_size_factor: f64,
+
+  /// Where to write the summary of the run, in addition to printing it
+  pub report: Option<Report>,
+}
@@ -0,0 +1,180 @@
+use std::ffi::OsString;
+use std::fs::{File, OpenOptions};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use fs2::FileExt;
+
+use crate::models::SessionId;
+
+/// Marks the name of an output that is still being encoded
+const PARTIAL_MARKER: &str = ".deo-partial";
+
+/// Partial outputs younger than this are never treated as stale, as they may still be written by another run without
+/// holding a lock, such as while it copies a file from the scratch directory
+const MIN_STALE_AGE: Duration = Duration::from_secs(60 * 60);
+
+/// Where a file is encoded to before it is moved to its output. Partial outputs are hidden files next to the output,
+/// or in a directory per session under the scratch directory.
+pub fn partial_path(output_file: &Path, session_id: &SessionId, scratch_dir: Option<&Path>) -> PathBuf {
+  let file_name = partial_file_name(output_file);
+
+  match scratch_dir {
+    Some(scratch_dir) => scratch_dir.join(session_id.id()).join(file_name),
+    None => output_file.with_file_name(file_name),
+  }
+}
+
+/// The extension is kept at the end of the name, as encoders such as ffmpeg choose the container by it.
+fn partial_file_name(output_file: &Path) -> OsString {
+  let stem = output_file.file_stem().unwrap_or_default().to_string_lossy();
+
+  match output_file.extension() {
+    Some(extension) => format!(".{}{}.{}", stem, PARTIAL_MARKER, extension.to_string_lossy()).into(),
+    None => format!(".{}{}", stem, PARTIAL_MARKER).into(),
+  }
+}
+
+pub fn is_partial(path: &Path) -> bool {
+  let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+
+  file_name.starts_with('.') && (file_name.ends_with(PARTIAL_MARKER) || file_name.contains(&format!("{}.", PARTIAL_MARKER)))
+}
+
+/// Create the partial output and lock it for as long as the returned file is open, so that other runs can tell it's
+/// still being encoded. Encoders truncate the partial output rather than replacing it, so the lock is kept. It isn't
+/// truncated here, as it may belong to another run that is encoding the same file. Returns None when another run
+/// holds the lock.
+pub fn lock(partial_file: &Path) -> std::io::Result<Option<File>> {
+  let file = OpenOptions::new().write(true).create(true).truncate(false).open(partial_file)?;
+
+  match file.try_lock_exclusive() {
+    Ok(_) => Ok(Some(file)),
+    Err(e) if e.kind() == fs2::lock_contended_error().kind() => Ok(None),
+    Err(e) => Err(e),
+  }
+}
+
+/// Move a finished partial output to its output. A partial output that can't be renamed, such as one in a scratch
+/// directory on another filesystem, is copied to a partial output next to the output first, so that the output only
+/// ever appears once it's complete.
+pub fn move_into_place(partial_file: &Path, output_file: &Path) -> std::io::Result<()> {
+  std::fs::rename(partial_file, output_file)
+    .or_else(|_| {
+      let staged_file = output_file.with_file_name(partial_file_name(output_file));
+
+      std::fs::copy(partial_file, &staged_file)
+        .and_then(|_| std::fs::rename(&staged_file, output_file))
+        .and_then(|_| std::fs::remove_file(partial_file))
+        .inspect_err(|_| {
+          let _ = std::fs::remove_file(&staged_file);
+        })
+    })
+}
+
+/// Delete the partial outputs left in the directories by runs that crashed or were killed. Partial outputs that are
+/// locked by a running encode, or that have changed recently, are left alone. Returns the result of deleting each
+/// stale partial output that was found.
+pub fn remove_stale(dirs: &[PathBuf]) -> Vec<(PathBuf, std::io::Result<()>)> {
+  dirs
+    .iter()
+    .filter_map(|dir| std::fs::read_dir(dir).ok())
+    .flat_map(|entries| entries.filter_map(Result::ok))
+    .map(|entry| entry.path())
+    .filter(|path| path.is_file() && is_partial(path) && is_old(path))
+    .filter_map(|path| {
+      // The lock is held while the partial output is deleted, so a run can't start encoding to it in between
+      let file = File::open(&path).ok()?;
+      file.try_lock_exclusive().ok()?;
+      let removed = std::fs::remove_file(&path);
+      Some((path, removed))
+    })
+    .collect()
+}
+
+fn is_old(path: &Path) -> bool {
+  std::fs::metadata(path)
+    .and_then(|metadata| metadata.modified())
+    .ok()
+    .and_then(|modified| SystemTime::now().duration_since(modified).ok())
+    .is_some_and(|age| age >= MIN_STALE_AGE)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use tempfile::TempDir;
+
+    #[test]
+    fn hides_partial_outputs_next_to_the_output() {
+      let output_file = Path::new("/media/Encodes/ThunderCats {tvdb-70355}/Season 01/S01E01 - Exodus.mp4");
+
+      assert_eq!(
+        partial_path(output_file, &SessionId::new("session1"), None),
+        PathBuf::from("/media/Encodes/ThunderCats {tvdb-70355}/Season 01/.S01E01 - Exodus.deo-partial.mp4")
+      )
+    }
+
+    #[test]
+    fn keeps_partial_outputs_by_session_in_the_scratch_dir() {
+      let output_file = Path::new("/media/Encodes/ThunderCats {tvdb-70355}/Season 01/S01E01 - Exodus.mp4");
+
+      assert_eq!(
+        partial_path(output_file, &SessionId::new("session1"), Some(Path::new("/scratch"))),
+        PathBuf::from("/scratch/session1/.S01E01 - Exodus.deo-partial.mp4")
+      )
+    }
+
+    #[test]
+    fn recognises_partial_outputs() {
+      assert!(is_partial(Path::new("/media/Encodes/.S01E01 - Exodus.deo-partial.mp4")));
+      assert!(is_partial(Path::new("/media/Encodes/.Exodus.deo-partial")));
+      assert!(!is_partial(Path::new("/media/Encodes/S01E01 - Exodus.mp4")));
+      assert!(!is_partial(Path::new("/media/Encodes/S01E01 - Exodus.deo-partial.mp4")));
+      assert!(!is_partial(Path::new("/media/Encodes/.S01E01 - Exodus.deo-partially.mp4")));
+    }
+
+    fn write_partial(dir: &Path, age: Duration) -> PathBuf {
+      let partial_file = dir.join(".S01E01 - Exodus.deo-partial.mp4");
+      let file = File::create(&partial_file).unwrap();
+      file.set_modified(SystemTime::now() - age).unwrap();
+      partial_file
+    }
+
+    #[test]
+    fn removes_old_partial_outputs_that_are_not_locked() {
+      let temp_dir = TempDir::new().unwrap();
+      let dir = temp_dir.path().to_path_buf();
+      let partial_file = write_partial(&dir, MIN_STALE_AGE * 2);
+
+      let removed: Vec<PathBuf> = remove_stale(std::slice::from_ref(&dir)).into_iter().map(|(path, _)| path).collect();
+
+      assert_eq!(removed, vec![partial_file.clone()]);
+      assert!(!partial_file.exists());
+    }
+
+    #[test]
+    fn keeps_partial_outputs_that_changed_recently() {
+      let temp_dir = TempDir::new().unwrap();
+      let dir = temp_dir.path().to_path_buf();
+      let partial_file = write_partial(&dir, Duration::from_secs(10));
+
+      assert!(remove_stale(std::slice::from_ref(&dir)).is_empty());
+      assert!(partial_file.exists());
+    }
+
+    #[test]
+    fn keeps_partial_outputs_that_are_being_encoded() {
+      let temp_dir = TempDir::new().unwrap();
+      let dir = temp_dir.path().to_path_buf();
+      let partial_file = write_partial(&dir, MIN_STALE_AGE * 2);
+      let locked_file = lock(&partial_file).unwrap().unwrap();
+      assert!(lock(&partial_file).unwrap().is_none());
+      locked_file.set_modified(SystemTime::now() - MIN_STALE_AGE * 2).unwrap();
+
+      assert!(remove_stale(std::slice::from_ref(&dir)).is_empty());
+      assert!(partial_file.exists());
+      drop(locked_file)
+    }
+}
@@ -0,0 +1,197 @@
+use std::collections::BTreeSet;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use chrono::{DateTime, Local};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use crate::error::{DeoEncodingError, LogFile};
+use crate::models::SessionId;
+use super::EncodeJob;
+
+/// The log of a single run of deo. Each run gets its own directory under the log directory, named after its run id,
+/// which holds a JSON-lines file with one record per file and the handbrake logs of each file.
+pub struct RunLog {
+  run_id: String,
+  dir: PathBuf,
+  file: Mutex<File>,
+  records: Mutex<Vec<RunLogRecord>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FileStatus {
+  Completed,
+  Failed,
+  VerificationFailed,
+  Skipped,
+  Cancelled,
+}
+
+/// A single line of the run log, describing what happened to one file.
+#[derive(Debug, Clone, Serialize)]
+pub struct RunLogRecord {
+  pub run_id: String,
+  pub session_id: SessionId,
+  pub profile: String,
+  pub attempt: u32,
+  pub input: PathBuf,
+  pub output: PathBuf,
+  pub status: FileStatus,
+  pub start_time: DateTime<Local>,
+  pub end_time: DateTime<Local>,
+  pub exit_code: Option<i32>,
+  pub input_size: Option<u64>,
+  pub output_size: Option<u64>,
+  pub log: Option<PathBuf>,
+  /// Why the file failed, when it isn't clear from the exit code
+  pub reason: Option<String>,
+}
+
+impl RunLogRecord {
+  /// A record for a job that has finished now. The sizes of the input and output are taken from the filesystem.
+  pub fn new(run_id: &str, job: &EncodeJob, output: &Path, status: FileStatus, start_time: DateTime<Local>) -> Self {
+    Self {
+      run_id: run_id.to_owned(),
+      session_id: job.session_id.clone(),
+      profile: job.profile.to_string(),
+      attempt: 1,
+      input: job.input.mkv_path.clone(),
+      output: output.to_owned(),
+      status,
+      start_time,
+      end_time: Local::now(),
+      exit_code: None,
+      input_size: file_size(&job.input.mkv_path),
+      output_size: file_size(output),
+      log: None,
+      reason: None,
+    }
+  }
+}
+
+impl RunLog {
+  pub fn create(log_dir: &Path) -> Result<Self, DeoEncodingError> {
+    let timestamp = Local::now().format("%Y%m%d-%H%M%S").to_string();
+
+    std::fs::create_dir_all(log_dir)
+      .map_err(|e| DeoEncodingError::CouldNotOpenLogFile(LogFile::new(log_dir), e.to_string()))?;
+
+    // Runs started within the same second get a counter, so they never share a log
+    let run_id =
+      std::iter::once(timestamp.clone())
+        .chain((1..).map(|counter| format!("{timestamp}-{counter}")))
+        .find(|run_id| !log_dir.join(run_id).exists())
+        .expect("Could not find an unused run id");
+
+    let dir = log_dir.join(&run_id);
+
+    std::fs::create_dir(&dir)
+      .map_err(|e| DeoEncodingError::CouldNotOpenLogFile(LogFile::new(&dir), e.to_string()))?;
+
+    let log_file_path = dir.join("run.jsonl");
+    let file =
+      OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&log_file_path)
+        .map_err(|e| DeoEncodingError::CouldNotOpenLogFile(LogFile::new(&log_file_path), e.to_string()))?;
+
+    Ok(
+      Self {
+        run_id,
+        dir,
+        file: Mutex::new(file),
+        records: Mutex::new(vec![]),
+      }
+    )
+  }
+
+  pub fn run_id(&self) -> &str {
+    &self.run_id
+  }
+
+  /// Directory for all the logs of this run.
+  pub fn dir(&self) -> &Path {
+    &self.dir
+  }
+
+  pub fn write(&self, record: &RunLogRecord) -> Result<(), DeoEncodingError> {
+    let log_file_path = self.dir.join("run.jsonl");
+    let line =
+      serde_json::to_string(record)
+        .map_err(|e| DeoEncodingError::CouldNotWriteLogFile(LogFile::new(&log_file_path), e.to_string()))?;
+
+    self.records.lock().unwrap().push(record.clone());
+
+    let mut file = self.file.lock().unwrap();
+    writeln!(file, "{}", line)
+      .and_then(|_| file.flush())
+      .map_err(|e| DeoEncodingError::CouldNotWriteLogFile(LogFile::new(&log_file_path), e.to_string()))
+  }
+
+  /// Every record written so far, in the order they were written.
+  pub fn records(&self) -> Vec<RunLogRecord> {
+    self.records.lock().unwrap().clone()
+  }
+}
+
+/// The parts of a run log record that show whether a file has been encoded.
+#[derive(Deserialize)]
+struct EncodedRecord {
+  input: PathBuf,
+  status: FileStatus,
+}
+
+/// Every line of every run log under the log directory that decodes as a T. Lines from older or damaged logs are
+/// skipped.
+pub fn read_history<T: DeserializeOwned>(log_dir: &Path) -> Vec<T> {
+  std::fs::read_dir(log_dir)
+    .into_iter()
+    .flat_map(|entries| entries.filter_map(Result::ok))
+    .filter_map(|entry| std::fs::read_to_string(entry.path().join("run.jsonl")).ok())
+    .flat_map(|run_log| {
+      run_log
+        .lines()
+        .filter_map(|line| serde_json::from_str::<T>(line).ok())
+        .collect::<Vec<_>>()
+    })
+    .collect()
+}
+
+/// The inputs that earlier runs have encoded. Skipped files aren't included, as a file skipped by a hook may be
+/// encoded by a later run.
+pub fn encoded_inputs(log_dir: &Path) -> BTreeSet<PathBuf> {
+  read_history::<EncodedRecord>(log_dir)
+    .into_iter()
+    .filter(|record| record.status == FileStatus::Completed)
+    .map(|record| record.input)
+    .collect()
+}
+
+fn file_size(path: &Path) -> Option<u64> {
+  std::fs::metadata(path)
+    .ok()
+    .map(|metadata| metadata.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn finds_the_inputs_that_were_encoded() {
+      assert_eq!(
+        encoded_inputs(Path::new("data/logs")),
+        BTreeSet::from([
+          PathBuf::from("/rips/session1/renames/S01E01 - Exodus.mkv"),
+          PathBuf::from("/rips/session1/renames/S01E02 - The Unholy Alliance.mkv"),
+          PathBuf::from("/rips/session2/renames/S01E03 - Pumm-Ra.mkv"),
+        ])
+      )
+    }
+}
@@ -0,0 +1,203 @@
+use std::path::Path;
+use std::process::Stdio;
+
+use serde::Deserialize;
+
+use crate::error::{DeoScanError, FileName, HandbrakeCommand};
+use crate::hb_output_parser::{Output, OutputParser};
+use super::command::HandbrakeCli;
+
+/// Handbrake reports durations in ticks of a 90kHz clock
+const TICKS_PER_SECOND: f64 = 90_000.0;
+
+/// Transfer characteristics of HDR video, as numbered by handbrake
+const TRANSFER_PQ: u64 = 16;
+const TRANSFER_HLG: u64 = 18;
+
+/// The titles handbrake found in a file, from the `JSON Title Set` block of `handbrakecli --scan --json`.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct TitleSet {
+  #[serde(default)]
+  pub title_list: Vec<Title>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct Title {
+  pub duration: TitleDuration,
+  #[serde(default)]
+  pub geometry: Option<Geometry>,
+  #[serde(default)]
+  pub video_codec: Option<String>,
+  #[serde(default)]
+  pub color: Option<Color>,
+  /// Only present for Dolby Vision sources
+  #[serde(default)]
+  pub dolby_vision_configuration_record: Option<serde_json::Value>,
+  #[serde(default)]
+  pub audio_list: Vec<AudioTrack>,
+  #[serde(default)]
+  pub subtitle_list: Vec<SubtitleTrack>,
+}
+
+#[derive(Debug, Clone, PartialEq, Default, Deserialize)]
+#[serde(default, rename_all = "PascalCase")]
+pub struct Geometry {
+  pub width: u64,
+  pub height: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Default, Deserialize)]
+#[serde(default, rename_all = "PascalCase")]
+pub struct Color {
+  pub transfer: Option<u64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Default, Deserialize)]
+#[serde(default, rename_all = "PascalCase")]
+pub struct AudioTrack {
+  pub codec_name: Option<String>,
+  pub channel_count: Option<u64>,
+  pub language_code: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Default, Deserialize)]
+#[serde(default, rename_all = "PascalCase")]
+pub struct SubtitleTrack {
+  pub source_name: Option<String>,
+  pub language_code: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Default, Deserialize)]
+#[serde(default, rename_all = "PascalCase")]
+pub struct TitleDuration {
+  pub hours: u64,
+  pub minutes: u64,
+  pub seconds: u64,
+  pub ticks: u64,
+}
+
+impl TitleDuration {
+  pub fn as_secs_f64(&self) -> f64 {
+    if self.ticks > 0 {
+      self.ticks as f64 / TICKS_PER_SECOND
+    } else {
+      (self.hours * 3600 + self.minutes * 60 + self.seconds) as f64
+    }
+  }
+}
+
+impl Title {
+  /// The HDR formats of the video, which is empty for SDR video.
+  pub fn hdr_formats(&self) -> Vec<&'static str> {
+    let transfer_format =
+      match self.color.as_ref().and_then(|color| color.transfer) {
+        Some(TRANSFER_PQ) => Some("HDR10"),
+        Some(TRANSFER_HLG) => Some("HLG"),
+        _ => None,
+      };
+
+    let dolby_vision = self.dolby_vision_configuration_record.is_some().then_some("Dolby Vision");
+
+    transfer_format.into_iter().chain(dolby_vision).collect()
+  }
+}
+
+impl TitleSet {
+  /// The first title of the file. Files produced by mkv-renamer and handbrake only ever have one.
+  pub fn first_title(&self) -> Option<&Title> {
+    self.title_list.first()
+  }
+}
+
+/// Scan a file with handbrake and return the titles it contains.
+pub fn scan(handbrake_cli: &HandbrakeCli, file: &Path) -> Result<TitleSet, DeoScanError> {
+  let output =
+    handbrake_cli
+      .scan_command(file)
+      .stdout(Stdio::piped())
+      .stderr(Stdio::null())
+      .output()
+      .map_err(|e| {
+        let command = format!("{} --scan --json -i {}", handbrake_cli.executable, file.to_string_lossy());
+        DeoScanError::FailedToSpawnHandbrake(HandbrakeCommand::new(command), e.to_string())
+      })?;
+
+  if !output.status.success() {
+    return Err(DeoScanError::ScanFailed(FileName::new(file), output.status.code()))
+  }
+
+  title_set_from_output(file, &String::from_utf8_lossy(&output.stdout))
+}
+
+/// Find and decode the title set in the output of a scan of a file.
+fn title_set_from_output(file: &Path, output: &str) -> Result<TitleSet, DeoScanError> {
+  let mut parser = OutputParser::new();
+
+  output
+    .lines()
+    .filter_map(|line| parser.parse(line))
+    .find_map(|block| match block {
+      Output::TitleSet(value) => Some(value),
+      _ => None,
+    })
+    .ok_or_else(|| DeoScanError::NoTitleSetFound(FileName::new(file)))
+    .and_then(|value| {
+      serde_json::from_value::<TitleSet>(value)
+        .map_err(|e| DeoScanError::CouldNotDecodeTitleSet(FileName::new(file), e.to_string()))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    const ENCODE_OUTPUT: &str = include_str!("../../data/handbrake/encode_output.txt");
+
+    #[test]
+    fn decodes_title_set_from_captured_output() {
+      let title_set = title_set_from_output(Path::new("S01E01 - Exodus.mkv"), ENCODE_OUTPUT).unwrap();
+      let duration = &title_set.first_title().unwrap().duration;
+
+      assert_eq!(duration, &TitleDuration { hours: 0, minutes: 22, seconds: 41, ticks: 122490000 });
+      assert_eq!(duration.as_secs_f64(), 1361.0)
+    }
+
+    #[test]
+    fn decodes_video_and_tracks_from_captured_output() {
+      let title_set = title_set_from_output(Path::new("S01E01 - Exodus.mkv"), ENCODE_OUTPUT).unwrap();
+      let title = title_set.first_title().unwrap();
+
+      assert_eq!(title.geometry, Some(Geometry { width: 1920, height: 1080 }));
+      assert_eq!(title.video_codec.as_deref(), Some("h264"));
+      assert_eq!(title.hdr_formats(), Vec::<&str>::new());
+      assert_eq!(title.audio_list, vec![AudioTrack { codec_name: Some("ac3".to_owned()), channel_count: Some(6), language_code: Some("eng".to_owned()) }]);
+      assert_eq!(title.subtitle_list, vec![SubtitleTrack { source_name: Some("PGS".to_owned()), language_code: Some("eng".to_owned()) }]);
+    }
+
+    #[test]
+    fn finds_hdr_formats() {
+      let title = |json: &str| serde_json::from_str::<Title>(&format!(r#"{{"Duration": {{}}{}}}"#, json)).unwrap();
+
+      assert_eq!(title(r#", "Color": {"Transfer": 16}"#).hdr_formats(), vec!["HDR10"]);
+      assert_eq!(title(r#", "Color": {"Transfer": 18}"#).hdr_formats(), vec!["HLG"]);
+      assert_eq!(title(r#", "Color": {"Transfer": 16}, "DolbyVisionConfigurationRecord": {"dv_profile": 8}"#).hdr_formats(), vec!["HDR10", "Dolby Vision"]);
+      assert_eq!(title("").hdr_formats(), Vec::<&str>::new());
+    }
+
+    #[test]
+    fn falls_back_to_whole_seconds_without_ticks() {
+      let duration = TitleDuration { hours: 1, minutes: 2, seconds: 3, ticks: 0 };
+
+      assert_eq!(duration.as_secs_f64(), 3723.0)
+    }
+
+    #[test]
+    fn fails_without_a_title_set() {
+      let result = title_set_from_output(Path::new("S01E01 - Exodus.mkv"), "Progress: {\"State\": \"IDLE\"}");
+
+      assert!(matches!(result, Err(DeoScanError::NoTitleSetFound(_))))
+    }
+}
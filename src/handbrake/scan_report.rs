@@ -0,0 +1,228 @@
+use std::path::PathBuf;
+use std::time::Duration;
+
+use clap::ValueEnum;
+use indicatif::{FormattedDuration, ProgressBar, ProgressStyle};
+use serde::Serialize;
+
+use crate::error::DeoScanError;
+use crate::models::{SessionId, SessionToEncodeDir};
+use super::command::HandbrakeCli;
+use super::scan::{self, TitleSet};
+use super::summary::aligned;
+
+/// The format of the output of deo scan.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ScanFormat {
+  /// A table per session, for reading
+  Table,
+  /// Every file, for other tools
+  Json,
+}
+
+/// What handbrake found in a source file.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ScannedFile {
+  pub session_id: SessionId,
+  pub input: PathBuf,
+  pub duration_seconds: Option<f64>,
+  pub width: Option<u64>,
+  pub height: Option<u64>,
+  pub video_codec: Option<String>,
+  pub hdr: Vec<String>,
+  pub audio_tracks: Vec<ScannedTrack>,
+  pub subtitle_tracks: Vec<ScannedTrack>,
+  /// Why the file couldn't be scanned
+  pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ScannedTrack {
+  pub language: Option<String>,
+  /// The codec of an audio track, or the source format of a subtitle track
+  pub format: Option<String>,
+  pub channels: Option<u64>,
+}
+
+impl ScannedFile {
+  fn new(session_id: SessionId, input: PathBuf, scan_result: Result<TitleSet, DeoScanError>) -> Self {
+    let scanned_file =
+      ScannedFile {
+        session_id,
+        input,
+        duration_seconds: None,
+        width: None,
+        height: None,
+        video_codec: None,
+        hdr: vec![],
+        audio_tracks: vec![],
+        subtitle_tracks: vec![],
+        error: None,
+      };
+
+    let title_set =
+      match scan_result {
+        Ok(title_set) => title_set,
+        Err(error) => return ScannedFile { error: Some(error.to_string()), ..scanned_file },
+      };
+
+    let Some(title) = title_set.first_title() else {
+      return ScannedFile { error: Some("handbrake did not find any titles".to_owned()), ..scanned_file }
+    };
+
+    let audio_tracks =
+      title
+        .audio_list
+        .iter()
+        .map(|track| ScannedTrack { language: track.language_code.clone(), format: track.codec_name.clone(), channels: track.channel_count })
+        .collect();
+
+    let subtitle_tracks =
+      title
+        .subtitle_list
+        .iter()
+        .map(|track| ScannedTrack { language: track.language_code.clone(), format: track.source_name.clone(), channels: None })
+        .collect();
+
+    ScannedFile {
+      duration_seconds: Some(title.duration.as_secs_f64()),
+      width: title.geometry.as_ref().map(|geometry| geometry.width),
+      height: title.geometry.as_ref().map(|geometry| geometry.height),
+      video_codec: title.video_codec.clone(),
+      hdr: title.hdr_formats().into_iter().map(str::to_owned).collect(),
+      audio_tracks,
+      subtitle_tracks,
+      ..scanned_file
+    }
+  }
+
+  fn row(&self) -> Vec<String> {
+    let file_name = self.input.file_name().unwrap_or_default().to_string_lossy().to_string();
+
+    if let Some(error) = &self.error {
+      return vec![file_name, format!("error: {}", error)]
+    }
+
+    let resolution =
+      match (self.width, self.height) {
+        (Some(width), Some(height)) => format!("{}x{}", width, height),
+        _ => "-".to_owned(),
+      };
+
+    vec![
+      file_name,
+      self.duration_seconds.map_or("-".to_owned(), |seconds| FormattedDuration(Duration::from_secs_f64(seconds)).to_string()),
+      resolution,
+      self.video_codec.clone().unwrap_or("-".to_owned()),
+      if self.hdr.is_empty() { "SDR".to_owned() } else { self.hdr.join(", ") },
+      tracks_text(&self.audio_tracks),
+      tracks_text(&self.subtitle_tracks),
+    ]
+  }
+}
+
+/// Tracks as their language, format and channels, such as `eng ac3 6ch, jpn aac 2ch`.
+fn tracks_text(tracks: &[ScannedTrack]) -> String {
+  if tracks.is_empty() {
+    return "-".to_owned()
+  }
+
+  tracks
+    .iter()
+    .map(|track| {
+      [track.language.clone().unwrap_or("und".to_owned())]
+        .into_iter()
+        .chain(track.format.clone())
+        .chain(track.channels.map(|channels| format!("{}ch", channels)))
+        .collect::<Vec<_>>()
+        .join(" ")
+    })
+    .collect::<Vec<_>>()
+    .join(", ")
+}
+
+/// Scan every rename of the sessions with handbrake, and print what was found in each.
+pub fn scan_sources(handbrake_cli: &HandbrakeCli, sessions: Vec<SessionToEncodeDir>, format: ScanFormat) {
+  let files: Vec<(SessionId, PathBuf)> =
+    sessions
+      .iter()
+      .flat_map(|sed| sed.rename_files().into_iter().map(|input| (sed.session_id(), input.mkv_path)))
+      .collect();
+
+  // Progress is drawn on stderr, which leaves stdout to the JSON output
+  let bar = ProgressBar::new(files.len() as u64);
+  bar.set_style(ProgressStyle::with_template("scanning {pos}/{len} {wide_msg}").unwrap());
+
+  let scanned_files: Vec<ScannedFile> =
+    files
+      .into_iter()
+      .map(|(session_id, input)| {
+        bar.set_message(input.file_name().unwrap_or_default().to_string_lossy().to_string());
+        let scan_result = scan::scan(handbrake_cli, &input);
+        bar.inc(1);
+        ScannedFile::new(session_id, input, scan_result)
+      })
+      .collect();
+
+  bar.finish_and_clear();
+
+  match format {
+    ScanFormat::Table => println!("{}", table(&scanned_files)),
+    ScanFormat::Json => println!("{}", serde_json::to_string_pretty(&scanned_files).unwrap_or_default()),
+  }
+}
+
+fn table(scanned_files: &[ScannedFile]) -> String {
+  let header = ["file", "duration", "resolution", "video", "hdr", "audio", "subtitles"].map(str::to_owned).to_vec();
+
+  let mut lines = vec![];
+  let mut sessions: Vec<&SessionId> = scanned_files.iter().map(|file| &file.session_id).collect();
+  sessions.dedup();
+
+  for session_id in sessions {
+    lines.push(session_id.id().to_owned());
+    let rows: Vec<Vec<String>> =
+      std::iter::once(header.clone())
+        .chain(scanned_files.iter().filter(|file| &file.session_id == session_id).map(ScannedFile::row))
+        .collect();
+    lines.extend(aligned(&rows).into_iter().map(|row| format!("  {}", row)));
+  }
+
+  lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use crate::error::FileName;
+
+    const ENCODE_OUTPUT: &str = include_str!("../../data/handbrake/encode_output.txt");
+
+    fn title_set() -> TitleSet {
+      let json = ENCODE_OUTPUT.split_once("JSON Title Set: ").unwrap().1.split("\nProgress: ").next().unwrap();
+      serde_json::from_str(json).unwrap()
+    }
+
+    #[test]
+    fn describes_the_video_and_tracks_of_a_file() {
+      let scanned_file = ScannedFile::new(SessionId::new("session1"), PathBuf::from("/Rips/session1/renames/S01E01 - Exodus.mkv"), Ok(title_set()));
+
+      assert_eq!(
+        scanned_file.row(),
+        vec!["S01E01 - Exodus.mkv", "00:22:41", "1920x1080", "h264", "SDR", "eng ac3 6ch", "eng PGS"]
+      )
+    }
+
+    #[test]
+    fn shows_why_a_file_could_not_be_scanned() {
+      let input = PathBuf::from("/Rips/session1/renames/S01E01 - Exodus.mkv");
+      let scanned_file = ScannedFile::new(SessionId::new("session1"), input.clone(), Err(DeoScanError::ScanFailed(FileName::new(&input), Some(2))));
+
+      assert_eq!(scanned_file.duration_seconds, None);
+      assert_eq!(
+        scanned_file.row(),
+        vec!["S01E01 - Exodus.mkv", "error: Could not scan: /Rips/session1/renames/S01E01 - Exodus.mkv, handbrake exited with: 2"]
+      )
+    }
+}
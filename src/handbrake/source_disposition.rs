@@ -0,0 +1,159 @@
+use std::fs::OpenOptions;
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+
+use chrono::Local;
+use clap::ValueEnum;
+use serde::Deserialize;
+
+use super::EncodeJob;
+
+/// What to do with the source of a file once it has been encoded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SourceAction {
+  /// Leave the source where it is
+  Keep,
+  /// Delete the source
+  Delete,
+  /// Move the source to the archive directory
+  Archive,
+}
+
+/// When the sources of encoded files are disposed of.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DisposeAfter {
+  /// As soon as each file has been encoded
+  File,
+  /// Once every file in a session has been encoded, and only if none of them failed
+  Session,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OnSuccess {
+  Keep,
+  Delete,
+  Archive(PathBuf),
+}
+
+/// What happens to the sources of files once they have been encoded.
+#[derive(Debug, Clone)]
+pub struct SourceDisposition {
+  pub on_success: OnSuccess,
+  pub dispose_after: DisposeAfter,
+  /// Where the sources of files that failed to encode are moved to, along with their logs
+  pub failed_dir: Option<PathBuf>,
+}
+
+impl SourceDisposition {
+  /// Returns `None` when sources should be archived but there is no archive directory.
+  pub fn new(action: SourceAction, dispose_after: DisposeAfter, archive_dir: Option<PathBuf>, failed_dir: Option<PathBuf>) -> Option<Self> {
+    let on_success =
+      match (action, archive_dir) {
+        (SourceAction::Keep, _) => OnSuccess::Keep,
+        (SourceAction::Delete, _) => OnSuccess::Delete,
+        (SourceAction::Archive, Some(archive_dir)) => OnSuccess::Archive(archive_dir),
+        (SourceAction::Archive, None) => return None,
+      };
+
+    Some(
+      Self {
+        on_success,
+        dispose_after,
+        failed_dir,
+      }
+    )
+  }
+}
+
+/// Delete or archive the source of a successfully encoded file. Returns what was done, if anything.
+pub fn dispose_completed(on_success: &OnSuccess, job: &EncodeJob) -> Result<Option<String>, String> {
+  let source = &job.input.mkv_path;
+
+  match on_success {
+    OnSuccess::Keep => Ok(None),
+    OnSuccess::Delete => {
+      std::fs::remove_file(source)
+        .map(|_| Some(format!("deleted {}", source.to_string_lossy())))
+        .map_err(|e| format!("could not delete {}: {}", source.to_string_lossy(), e))
+    },
+    OnSuccess::Archive(archive_dir) => move_to_dir(source, &session_dir(archive_dir, job)).map(|action| Some(format!("archived {}", action))),
+  }
+}
+
+/// Move the source of a file that failed to encode into the failed directory, along with copies of its logs.
+pub fn dispose_failed(failed_dir: &Path, job: &EncodeJob, logs: &[PathBuf]) -> Result<String, String> {
+  let session_dir = session_dir(failed_dir, job);
+  let action = move_to_dir(&job.input.mkv_path, &session_dir)?;
+
+  for log in logs.iter().filter(|log| log.exists()) {
+    let log_copy = session_dir.join(log.file_name().unwrap_or_default());
+    std::fs::copy(log, &log_copy)
+      .map_err(|e| format!("could not copy {} to {}: {}", log.to_string_lossy(), log_copy.to_string_lossy(), e))?;
+  }
+
+  Ok(format!("moved failed {}", action))
+}
+
+/// Append what was done to a source to the sources log of the run, so there's a record of every source deo touched.
+pub fn log_action(log_dir: &Path, action: &str) -> Result<(), String> {
+  let sources_log = log_dir.join("sources.log");
+
+  OpenOptions::new()
+    .create(true)
+    .append(true)
+    .open(&sources_log)
+    .and_then(|mut file| writeln!(file, "{} {}", Local::now().format("%Y-%m-%d %H:%M:%S"), action))
+    .map_err(|e| format!("could not write to {}: {}", sources_log.to_string_lossy(), e))
+}
+
+/// Sources are kept apart by session, as episode names can repeat across sessions.
+fn session_dir(dir: &Path, job: &EncodeJob) -> PathBuf {
+  dir.join(job.session_id.id())
+}
+
+fn move_to_dir(source: &Path, dir: &Path) -> Result<String, String> {
+  let destination = dir.join(source.file_name().unwrap_or_default());
+  let describe_error = |e: std::io::Error| format!("could not move {} to {}: {}", source.to_string_lossy(), destination.to_string_lossy(), e);
+
+  if destination.exists() {
+    return Err(describe_error(std::io::Error::new(ErrorKind::AlreadyExists, "the destination already exists")))
+  }
+
+  std::fs::create_dir_all(dir)
+    .and_then(|_| move_file(source, &destination))
+    .map(|_| format!("{} to {}", source.to_string_lossy(), destination.to_string_lossy()))
+    .map_err(describe_error)
+}
+
+/// Rename the file, or copy it and remove the original when the destination is on another filesystem.
+fn move_file(source: &Path, destination: &Path) -> std::io::Result<()> {
+  std::fs::rename(source, destination)
+    .or_else(|_| {
+      std::fs::copy(source, destination)
+        .and_then(|_| std::fs::remove_file(source))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn archiving_requires_an_archive_dir() {
+      assert!(SourceDisposition::new(SourceAction::Archive, DisposeAfter::File, None, None).is_none());
+
+      let disposition = SourceDisposition::new(SourceAction::Archive, DisposeAfter::Session, Some(PathBuf::from("/media/archive")), None).unwrap();
+      assert_eq!(disposition.on_success, OnSuccess::Archive(PathBuf::from("/media/archive")));
+      assert_eq!(disposition.dispose_after, DisposeAfter::Session);
+    }
+
+    #[test]
+    fn ignores_the_archive_dir_unless_archiving() {
+      let disposition = SourceDisposition::new(SourceAction::Delete, DisposeAfter::File, Some(PathBuf::from("/media/archive")), None).unwrap();
+
+      assert_eq!(disposition.on_success, OnSuccess::Delete)
+    }
+}
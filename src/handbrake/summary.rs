@@ -0,0 +1,462 @@
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use chrono::{DateTime, Local};
+use clap::ValueEnum;
+use indicatif::{FormattedDuration, HumanBytes};
+use serde::Serialize;
+
+use crate::error::{DeoEncodingError, FileName};
+use crate::models::SessionId;
+use super::run_log::{FileStatus, RunLogRecord};
+
+/// The format of the report written by --report.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ReportFormat {
+  /// A table per session and the totals, for reading
+  Markdown,
+  /// Every file and the totals, for other tools
+  Json,
+}
+
+impl ReportFormat {
+  /// The format suggested by the extension of a report file, which is Markdown unless the extension is json.
+  pub fn for_path(path: &Path) -> Self {
+    match path.extension().and_then(|extension| extension.to_str()) {
+      Some(extension) if extension.eq_ignore_ascii_case("json") => ReportFormat::Json,
+      _ => ReportFormat::Markdown,
+    }
+  }
+}
+
+/// Where to write the summary of a run, and in what format.
+#[derive(Debug, Clone)]
+pub struct Report {
+  pub path: PathBuf,
+  pub format: ReportFormat,
+}
+
+/// What happened to every file of a run, by session.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RunSummary {
+  pub run_id: String,
+  pub sessions: Vec<SessionSummary>,
+  pub totals: SummaryTotals,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SessionSummary {
+  pub session_id: SessionId,
+  pub files: Vec<FileSummary>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct FileSummary {
+  pub input: PathBuf,
+  pub output: PathBuf,
+  pub profile: String,
+  pub status: FileStatus,
+  pub attempts: u32,
+  /// Seconds from the start of the first attempt to the end of the last attempt
+  pub wall_time_seconds: i64,
+  pub input_size: Option<u64>,
+  pub output_size: Option<u64>,
+  /// Size of the input divided by the size of the output, for completed files
+  pub compression_ratio: Option<f64>,
+}
+
+/// Totals across every file of a run. Sizes and the compression ratio only count completed files.
+#[derive(Debug, Clone, PartialEq, Default, Serialize)]
+pub struct SummaryTotals {
+  pub files: usize,
+  pub completed: usize,
+  pub failed: usize,
+  pub verification_failed: usize,
+  pub skipped: usize,
+  pub cancelled: usize,
+  /// Seconds from the start of the first file to the end of the last file
+  pub wall_time_seconds: i64,
+  pub input_size: u64,
+  pub output_size: u64,
+  pub compression_ratio: Option<f64>,
+}
+
+impl RunSummary {
+  /// Summarise the records of a run. A file that was attempted more than once has a record per attempt, which are
+  /// combined into one file with the outcome of its last attempt.
+  pub fn from_records(run_id: &str, records: &[RunLogRecord]) -> Self {
+    let mut sessions: Vec<SessionSummary> = vec![];
+
+    for (session_id, input) in unique_files(records) {
+      let attempts: Vec<&RunLogRecord> =
+        records
+          .iter()
+          .filter(|record| record.session_id == session_id && record.input == input)
+          .collect();
+
+      let file = file_summary(&attempts);
+
+      match sessions.iter_mut().find(|session| session.session_id == session_id) {
+        Some(session) => session.files.push(file),
+        None => sessions.push(SessionSummary { session_id, files: vec![file] }),
+      }
+    }
+
+    let totals = totals(&sessions, records);
+
+    Self {
+      run_id: run_id.to_owned(),
+      sessions,
+      totals,
+    }
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.sessions.is_empty()
+  }
+
+  /// A plain text table for the terminal.
+  pub fn table(&self) -> String {
+    let header = ["file", "status", "time", "input", "output", "ratio"].map(str::to_owned).to_vec();
+
+    let mut lines = vec![];
+    for session in &self.sessions {
+      lines.push(session.session_id.id().to_owned());
+      let rows: Vec<Vec<String>> = std::iter::once(header.clone()).chain(session.files.iter().map(file_row)).collect();
+      lines.extend(aligned(&rows).into_iter().map(|row| format!("  {}", row)));
+    }
+
+    lines.push(self.totals_text());
+    lines.join("\n")
+  }
+
+  pub fn markdown(&self) -> String {
+    let mut lines = vec![format!("# deo run {}", self.run_id)];
+
+    for session in &self.sessions {
+      lines.push(String::new());
+      lines.push(format!("## {}", session.session_id.id()));
+      lines.push(String::new());
+      lines.push("| File | Profile | Status | Attempts | Time | Input | Output | Ratio |".to_owned());
+      lines.push("| --- | --- | --- | ---: | ---: | ---: | ---: | ---: |".to_owned());
+
+      for file in &session.files {
+        let cells =
+          [
+            file_name(&file.input),
+            file.profile.clone(),
+            status_text(file.status).to_owned(),
+            file.attempts.to_string(),
+            duration_text(file.wall_time_seconds),
+            size_text(file.input_size),
+            size_text(file.output_size),
+            ratio_text(file.compression_ratio),
+          ];
+
+        lines.push(format!("| {} |", cells.map(|cell| cell.replace('|', "\\|")).join(" | ")))
+      }
+    }
+
+    lines.push(String::new());
+    lines.push("## Totals".to_owned());
+    lines.push(String::new());
+    lines.push(self.totals_text());
+    lines.push(String::new());
+    lines.join("\n")
+  }
+
+  /// Write the summary to a report file.
+  pub fn write_report(&self, report: &Report) -> Result<(), DeoEncodingError> {
+    let contents =
+      match report.format {
+        ReportFormat::Markdown => Ok(self.markdown()),
+        ReportFormat::Json => serde_json::to_string_pretty(self).map_err(|e| e.to_string()),
+      };
+
+    contents
+      .and_then(|contents| std::fs::write(&report.path, contents).map_err(|e| e.to_string()))
+      .map_err(|e| DeoEncodingError::CouldNotWriteReport(FileName::new(&report.path), e))
+  }
+
+  fn totals_text(&self) -> String {
+    let totals = &self.totals;
+    let counts =
+      [
+        (totals.completed, "completed"),
+        (totals.failed, "failed"),
+        (totals.verification_failed, "verification failed"),
+        (totals.skipped, "skipped"),
+        (totals.cancelled, "cancelled"),
+      ]
+      .into_iter()
+      .filter(|(count, _)| *count > 0)
+      .map(|(count, status)| format!("{} {}", count, status))
+      .collect::<Vec<_>>()
+      .join(", ");
+
+    format!(
+      "{} files ({}) in {}, {} encoded to {} ({})",
+      totals.files,
+      counts,
+      duration_text(totals.wall_time_seconds),
+      HumanBytes(totals.input_size),
+      HumanBytes(totals.output_size),
+      ratio_text(totals.compression_ratio)
+    )
+  }
+}
+
+/// Every file in the records once, in the order they were first seen.
+fn unique_files(records: &[RunLogRecord]) -> Vec<(SessionId, PathBuf)> {
+  let mut files: Vec<(SessionId, PathBuf)> = vec![];
+
+  for record in records {
+    if !files.iter().any(|(session_id, input)| session_id == &record.session_id && input == &record.input) {
+      files.push((record.session_id.clone(), record.input.clone()))
+    }
+  }
+
+  files
+}
+
+/// Combine the records of every attempt at a file.
+fn file_summary(attempts: &[&RunLogRecord]) -> FileSummary {
+  let last = attempts.last().expect("a file always has a record");
+
+  let start_time = attempts.iter().map(|record| record.start_time).min().unwrap_or(last.start_time);
+  let end_time = attempts.iter().map(|record| record.end_time).max().unwrap_or(last.end_time);
+
+  let compression_ratio =
+    match (last.status, last.input_size, last.output_size) {
+      (FileStatus::Completed, Some(input_size), Some(output_size)) => ratio(input_size, output_size),
+      _ => None,
+    };
+
+  FileSummary {
+    input: last.input.clone(),
+    output: last.output.clone(),
+    profile: last.profile.clone(),
+    status: last.status,
+    attempts: last.attempt,
+    wall_time_seconds: seconds_between(start_time, end_time),
+    input_size: last.input_size,
+    output_size: last.output_size,
+    compression_ratio,
+  }
+}
+
+fn totals(sessions: &[SessionSummary], records: &[RunLogRecord]) -> SummaryTotals {
+  let files: Vec<&FileSummary> = sessions.iter().flat_map(|session| &session.files).collect();
+  let count = |status: FileStatus| files.iter().filter(|file| file.status == status).count();
+
+  let completed: Vec<&&FileSummary> = files.iter().filter(|file| file.status == FileStatus::Completed).collect();
+  let input_size = completed.iter().filter_map(|file| file.input_size).sum();
+  let output_size = completed.iter().filter_map(|file| file.output_size).sum();
+
+  let start_time = records.iter().map(|record| record.start_time).min();
+  let end_time = records.iter().map(|record| record.end_time).max();
+
+  SummaryTotals {
+    files: files.len(),
+    completed: completed.len(),
+    failed: count(FileStatus::Failed),
+    verification_failed: count(FileStatus::VerificationFailed),
+    skipped: count(FileStatus::Skipped),
+    cancelled: count(FileStatus::Cancelled),
+    wall_time_seconds: start_time.zip(end_time).map_or(0, |(start_time, end_time)| seconds_between(start_time, end_time)),
+    input_size,
+    output_size,
+    compression_ratio: ratio(input_size, output_size),
+  }
+}
+
+fn ratio(input_size: u64, output_size: u64) -> Option<f64> {
+  (output_size > 0).then(|| input_size as f64 / output_size as f64)
+}
+
+fn seconds_between(start_time: DateTime<Local>, end_time: DateTime<Local>) -> i64 {
+  (end_time - start_time).num_seconds().max(0)
+}
+
+fn file_row(file: &FileSummary) -> Vec<String> {
+  vec![
+    file_name(&file.input),
+    status_text(file.status).to_owned(),
+    duration_text(file.wall_time_seconds),
+    size_text(file.input_size),
+    size_text(file.output_size),
+    ratio_text(file.compression_ratio),
+  ]
+}
+
+/// Pad every column to the width of its widest cell.
+pub(super) fn aligned(rows: &[Vec<String>]) -> Vec<String> {
+  let column_count = rows.iter().map(Vec::len).max().unwrap_or(0);
+  let widths: Vec<usize> =
+    (0..column_count)
+      .map(|column| rows.iter().filter_map(|row| row.get(column)).map(|cell| cell.chars().count()).max().unwrap_or(0))
+      .collect();
+
+  rows
+    .iter()
+    .map(|row| {
+      row
+        .iter()
+        .zip(&widths)
+        .map(|(cell, width)| format!("{:width$}", cell, width = width))
+        .collect::<Vec<_>>()
+        .join("  ")
+        .trim_end()
+        .to_owned()
+    })
+    .collect()
+}
+
+fn file_name(path: &Path) -> String {
+  path
+    .file_name()
+    .unwrap_or(path.as_os_str())
+    .to_string_lossy()
+    .to_string()
+}
+
+fn status_text(status: FileStatus) -> &'static str {
+  match status {
+    FileStatus::Completed => "completed",
+    FileStatus::Failed => "failed",
+    FileStatus::VerificationFailed => "verification failed",
+    FileStatus::Skipped => "skipped",
+    FileStatus::Cancelled => "cancelled",
+  }
+}
+
+fn duration_text(seconds: i64) -> String {
+  FormattedDuration(Duration::from_secs(seconds.max(0) as u64)).to_string()
+}
+
+fn size_text(size: Option<u64>) -> String {
+  size.map_or("-".to_owned(), |size| HumanBytes(size).to_string())
+}
+
+fn ratio_text(ratio: Option<f64>) -> String {
+  ratio.map_or("-".to_owned(), |ratio| format!("{:.2}x", ratio))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use pretty_assertions::assert_eq;
+
+    fn record(session: &str, input: &str, attempt: u32, status: FileStatus, minutes: (u32, u32), sizes: (Option<u64>, Option<u64>)) -> RunLogRecord {
+      let time = |minute: u32| Local.with_ymd_and_hms(2024, 5, 1, 20, minute, 0).unwrap();
+
+      RunLogRecord {
+        run_id: "20240501-200000".to_owned(),
+        session_id: SessionId::new(session),
+        profile: "Fast 1080p".to_owned(),
+        attempt,
+        input: PathBuf::from(format!("/rips/{}/{}", session, input)),
+        output: PathBuf::from(format!("/encodes/{}", input.replace(".mkv", ".mp4"))),
+        status,
+        start_time: time(minutes.0),
+        end_time: time(minutes.1),
+        exit_code: None,
+        input_size: sizes.0,
+        output_size: sizes.1,
+        log: None,
+        reason: None,
+      }
+    }
+
+    /// The session, file name, status, attempts, wall time and compression ratio of a file
+    type FileRow<'a> = (&'a str, String, FileStatus, u32, i64, Option<f64>);
+
+    fn records() -> Vec<RunLogRecord> {
+      vec![
+        record("session1", "title_t00.mkv", 1, FileStatus::Completed, (0, 10), (Some(4000), Some(1000))),
+        record("session2", "title_t00.mkv", 1, FileStatus::Skipped, (1, 1), (Some(3000), Some(2000))),
+        record("session1", "title_t01.mkv", 1, FileStatus::Failed, (10, 12), (Some(6000), None)),
+        record("session1", "title_t01.mkv", 2, FileStatus::Completed, (12, 30), (Some(6000), Some(2000))),
+        record("session1", "title_t02.mkv", 1, FileStatus::Failed, (30, 31), (Some(5000), None)),
+      ]
+    }
+
+    #[test]
+    fn combines_the_attempts_at_each_file_by_session() {
+      let summary = RunSummary::from_records("20240501-200000", &records());
+
+      let files: Vec<FileRow> =
+        summary
+          .sessions
+          .iter()
+          .flat_map(|session| session.files.iter().map(move |file| (session.session_id.id(), file_name(&file.input), file.status, file.attempts, file.wall_time_seconds, file.compression_ratio)))
+          .collect();
+
+      let expected =
+        vec![
+          ("session1", "title_t00.mkv".to_owned(), FileStatus::Completed, 1, 600, Some(4.0)),
+          ("session1", "title_t01.mkv".to_owned(), FileStatus::Completed, 2, 1200, Some(3.0)),
+          ("session1", "title_t02.mkv".to_owned(), FileStatus::Failed, 1, 60, None),
+          ("session2", "title_t00.mkv".to_owned(), FileStatus::Skipped, 1, 0, None),
+        ];
+
+      assert_eq!(files, expected)
+    }
+
+    #[test]
+    fn totals_only_count_the_sizes_of_completed_files() {
+      let totals = RunSummary::from_records("20240501-200000", &records()).totals;
+
+      let expected =
+        SummaryTotals {
+          files: 4,
+          completed: 2,
+          failed: 1,
+          verification_failed: 0,
+          skipped: 1,
+          cancelled: 0,
+          wall_time_seconds: 1860,
+          input_size: 10000,
+          output_size: 3000,
+          compression_ratio: Some(10000.0 / 3000.0),
+        };
+
+      assert_eq!(totals, expected)
+    }
+
+    #[test]
+    fn writes_a_markdown_table_per_session() {
+      let markdown = RunSummary::from_records("20240501-200000", &records()[..2]).markdown();
+
+      let expected = "\
+# deo run 20240501-200000
+
+## session1
+
+| File | Profile | Status | Attempts | Time | Input | Output | Ratio |
+| --- | --- | --- | ---: | ---: | ---: | ---: | ---: |
+| title_t00.mkv | Fast 1080p | completed | 1 | 00:10:00 | 3.91 KiB | 1000 B | 4.00x |
+
+## session2
+
+| File | Profile | Status | Attempts | Time | Input | Output | Ratio |
+| --- | --- | --- | ---: | ---: | ---: | ---: | ---: |
+| title_t00.mkv | Fast 1080p | skipped | 1 | 00:00:00 | 2.93 KiB | 1.95 KiB | - |
+
+## Totals
+
+2 files (1 completed, 1 skipped) in 00:10:00, 3.91 KiB encoded to 1000 B (4.00x)
+";
+
+      assert_eq!(markdown, expected)
+    }
+
+    #[test]
+    fn chooses_the_report_format_from_the_extension() {
+      assert_eq!(ReportFormat::for_path(Path::new("/notes/run.json")), ReportFormat::Json);
+      assert_eq!(ReportFormat::for_path(Path::new("/notes/run.JSON")), ReportFormat::Json);
+      assert_eq!(ReportFormat::for_path(Path::new("/notes/run.md")), ReportFormat::Markdown);
+      assert_eq!(ReportFormat::for_path(Path::new("/notes/run")), ReportFormat::Markdown);
+    }
+}
@@ -0,0 +1,46 @@
+use std::path::Path;
+
+use super::backend::Encoder;
+
+/// Check that an encode produced a usable output: the output must exist, must not be empty and must be as long as
+/// its source, give or take the tolerance. Returns why the output is not usable otherwise.
+pub fn verify_output(encoder: &dyn Encoder, input_file: &Path, output_file: &Path, tolerance_seconds: u64) -> Result<(), String> {
+  match std::fs::metadata(output_file) {
+    Err(_) => return Err("the output file does not exist".to_owned()),
+    Ok(metadata) if metadata.len() == 0 => return Err("the output file is empty".to_owned()),
+    Ok(_) => (),
+  }
+
+  let input_duration = encoder.duration(input_file).map_err(|e| format!("could not get the duration of the source: {}", e))?;
+  let output_duration = encoder.duration(output_file).map_err(|e| format!("could not get the duration of the output: {}", e))?;
+
+  check_duration(input_duration, output_duration, tolerance_seconds)
+}
+
+fn check_duration(input_duration: f64, output_duration: f64, tolerance_seconds: u64) -> Result<(), String> {
+  if (input_duration - output_duration).abs() <= tolerance_seconds as f64 {
+    Ok(())
+  } else {
+    Err(format!("the output is {:.1}s long but the source is {:.1}s long", output_duration, input_duration))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn accepts_durations_within_tolerance() {
+      assert_eq!(check_duration(1361.0, 1360.2, 1), Ok(()));
+      assert_eq!(check_duration(1361.0, 1361.0, 0), Ok(()));
+    }
+
+    #[test]
+    fn rejects_durations_outside_tolerance() {
+      assert_eq!(
+        check_duration(1361.0, 601.5, 2),
+        Err("the output is 601.5s long but the source is 1361.0s long".to_owned())
+      )
+    }
+}
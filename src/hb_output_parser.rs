@@ -1,30 +1,277 @@
+use serde::Deserialize;
+use serde_json::Value;
+
+/// A complete block of `handbrakecli --json` output, such as `Progress: {...}` or `JSON Title Set: {...}`.
+#[derive(Debug, Clone, PartialEq)]
 pub enum Output {
-  Progress(f32),
-  Pass(u32),
-  Ignore,
-  Done(u32),
-}
-
-pub fn parse(line: &str) -> Output {
-  if line.starts_with("        \"Progress\":") {
-    let pieces: Vec<_> = line.split(':').collect();
-    let progress_str = pieces.get(1).map(|v| v.to_string()).take().unwrap_or_else(|| format!("Expected 2 tokens but got: {:?}", pieces));
-    // Remove the trailing ",""
-    let progress = &progress_str[..progress_str.len() - 1].trim().parse::<f32>().unwrap() * 100.0;
-    Output::Progress(progress)
-  } else if line.starts_with("        \"Pass\":") {
-    let pieces: Vec<_> = line.split(':').collect();
-    let pass_str = pieces.get(1).map(|v| v.to_string()).take().unwrap_or_else(|| format!("Expected 2 tokens but got: {:?}", pieces));
-    // Remove the trailing ",""
-    let pass = &pass_str[..pass_str.len() - 1].trim().parse::<u32>().unwrap();
-    Output::Pass(*pass)
-  } else if line.starts_with("  :      \"Error\":") {
-    let pieces: Vec<_> = line.split(':').collect();
-    let progress_str = pieces.get(1).map(|v| v.to_string()).take().unwrap_or_else(|| format!("Expected 2 tokens but got: {:?}", pieces));
-    // Remove the trailing ",""
-    let errors = &progress_str[..progress_str.len() - 1].trim().parse::<u32>().unwrap();
-    Output::Done(*errors)
+  Progress(EncoderState),
+  TitleSet(Value),
+  Other(String, Value),
+  Malformed(String, String),
+}
+
+/// The state of an encode, as reported by a `Progress: {...}` block of handbrakecli or by the progress of ffmpeg.
+#[derive(Debug, Clone, PartialEq)]
+pub enum EncoderState {
+  Scanning(Scanning),
+  Working(Working),
+  Muxing(Muxing),
+  WorkDone(WorkDone),
+  /// States we don't display, such as IDLE or PAUSED
+  Other(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Default, Deserialize)]
+#[serde(default, rename_all = "PascalCase")]
+pub struct Scanning {
+  pub progress: f64,
+  pub title: u32,
+  pub title_count: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Default, Deserialize)]
+#[serde(default, rename_all = "PascalCase")]
+pub struct Working {
+  pub progress: f64,
+  pub pass: u32,
+  pub pass_count: u32,
+  pub rate: f64,
+  pub rate_avg: f64,
+  #[serde(rename = "ETASeconds")]
+  pub eta_seconds: i64,
+}
+
+#[derive(Debug, Clone, PartialEq, Default, Deserialize)]
+#[serde(default, rename_all = "PascalCase")]
+pub struct Muxing {
+  pub progress: f64,
+}
+
+#[derive(Debug, Clone, PartialEq, Default, Deserialize)]
+#[serde(default, rename_all = "PascalCase")]
+pub struct WorkDone {
+  pub error: i64,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct ProgressBlock {
+  state: String,
+  scanning: Option<Scanning>,
+  working: Option<Working>,
+  muxing: Option<Muxing>,
+  work_done: Option<WorkDone>,
+}
+
+/// Incrementally parses `handbrakecli --json` output one line at a time.
+///
+/// Handbrake writes each piece of output as a label followed by a pretty printed JSON object that spans many lines.
+/// Lines are collected until the braces of the object balance, at which point the whole object is decoded. This
+/// doesn't rely on how handbrake indents its output.
+#[derive(Debug, Default)]
+pub struct OutputParser {
+  label: Option<String>,
+  buffer: String,
+  depth: usize,
+  in_string: bool,
+  escaped: bool,
+}
+
+impl OutputParser {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Feed the next line of output. Returns the block once the line that completes it has been seen.
+  pub fn parse(&mut self, line: &str) -> Option<Output> {
+    match (&self.label, block_start(line)) {
+      // A new block always starts over, in case the previous block was truncated
+      (_, Some((label, json))) => {
+        self.start_block(label);
+        self.push(json)
+      },
+      (Some(_), None) => self.push(line),
+      (None, None) => None,
+    }
+  }
+
+  fn start_block(&mut self, label: &str) {
+    self.label = Some(label.to_owned());
+    self.buffer.clear();
+    self.depth = 0;
+    self.in_string = false;
+    self.escaped = false;
+  }
+
+  fn push(&mut self, text: &str) -> Option<Output> {
+    for c in text.chars() {
+      self.buffer.push(c);
+
+      if self.in_string {
+        match c {
+          _ if self.escaped => self.escaped = false,
+          '\\' => self.escaped = true,
+          '"' => self.in_string = false,
+          _ => (),
+        }
+      } else {
+        match c {
+          '"' => self.in_string = true,
+          '{' | '[' => self.depth += 1,
+          '}' | ']' => {
+            self.depth = self.depth.saturating_sub(1);
+            if self.depth == 0 {
+              return self.finish_block()
+            }
+          },
+          _ => (),
+        }
+      }
+    }
+
+    self.buffer.push('\n');
+    None
+  }
+
+  fn finish_block(&mut self) -> Option<Output> {
+    let label = self.label.take()?;
+    let json = std::mem::take(&mut self.buffer);
+
+    let output = match serde_json::from_str::<Value>(&json) {
+      Ok(value) => to_output(&label, value),
+      Err(e) => Output::Malformed(label, e.to_string()),
+    };
+
+    Some(output)
+  }
+}
+
+/// Returns the label and the start of the JSON object, if the line starts a block. eg. `Progress: {`
+fn block_start(line: &str) -> Option<(&str, &str)> {
+  let (label, rest) = line.split_once(':')?;
+  let label = label.trim();
+  let rest = rest.trim_start();
+
+  let is_label = !label.is_empty() && label.chars().all(|c| c.is_ascii_alphanumeric() || c == ' ');
+
+  if is_label && rest.starts_with('{') {
+    Some((label, rest))
   } else {
-    Output::Ignore
+    None
   }
 }
+
+fn to_output(label: &str, value: Value) -> Output {
+  match label {
+    "Progress" => {
+      match serde_json::from_value::<ProgressBlock>(value) {
+        Ok(progress) => Output::Progress(to_state(progress)),
+        Err(e) => Output::Malformed(label.to_owned(), e.to_string()),
+      }
+    },
+    "JSON Title Set" => Output::TitleSet(value),
+    _ => Output::Other(label.to_owned(), value),
+  }
+}
+
+fn to_state(progress: ProgressBlock) -> EncoderState {
+  match progress.state.as_str() {
+    "SCANNING" => EncoderState::Scanning(progress.scanning.unwrap_or_default()),
+    "WORKING" => EncoderState::Working(progress.working.unwrap_or_default()),
+    "MUXING" => EncoderState::Muxing(progress.muxing.unwrap_or_default()),
+    "WORKDONE" => EncoderState::WorkDone(progress.work_done.unwrap_or_default()),
+    other => EncoderState::Other(other.to_owned()),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    const ENCODE_OUTPUT: &str = include_str!("../data/handbrake/encode_output.txt");
+
+    fn parse_all(output: &str) -> Vec<Output> {
+      let mut parser = OutputParser::new();
+      output
+        .lines()
+        .filter_map(|line| parser.parse(line))
+        .collect()
+    }
+
+    fn states(output: &str) -> Vec<EncoderState> {
+      parse_all(output)
+        .into_iter()
+        .filter_map(|o| match o {
+          Output::Progress(state) => Some(state),
+          _ => None
+        })
+        .collect()
+    }
+
+    #[test]
+    fn parses_captured_encode_output() {
+      let outputs = parse_all(ENCODE_OUTPUT);
+
+      assert_eq!(outputs.len(), 9);
+      assert!(matches!(&outputs[0], Output::Other(label, _) if label == "Version"));
+      assert!(matches!(&outputs[3], Output::TitleSet(_)));
+      assert!(!outputs.iter().any(|o| matches!(o, Output::Malformed(..))));
+    }
+
+    #[test]
+    fn parses_progress_states_from_captured_output() {
+      let states = states(ENCODE_OUTPUT);
+
+      let expected =
+        vec![
+          EncoderState::Scanning(Scanning { progress: 0.0, title: 1, title_count: 1 }),
+          EncoderState::Scanning(Scanning { progress: 1.0, title: 1, title_count: 1 }),
+          EncoderState::Working(Working { progress: 0.0, pass: 1, pass_count: 2, rate: 0.0, rate_avg: 0.0, eta_seconds: 0 }),
+          EncoderState::Working(Working { progress: 0.25431, pass: 1, pass_count: 2, rate: 81.327438, rate_avg: 79.846321, eta_seconds: 412 }),
+          EncoderState::Working(Working { progress: 0.76125, pass: 2, pass_count: 2, rate: 88.104523, rate_avg: 85.220001, eta_seconds: 130 }),
+          EncoderState::Muxing(Muxing { progress: 0.0 }),
+          EncoderState::WorkDone(WorkDone { error: 0 }),
+        ];
+
+      assert_eq!(states, expected)
+    }
+
+    #[test]
+    fn does_not_depend_on_indentation() {
+      let output = "Progress: {\n\"State\": \"WORKING\",\n  \"Working\": {\"Pass\": 1, \"PassCount\": 1,\n\"Progress\": 0.5}}";
+
+      assert_eq!(
+        states(output),
+        vec![EncoderState::Working(Working { progress: 0.5, pass: 1, pass_count: 1, ..Working::default() })]
+      )
+    }
+
+    #[test]
+    fn ignores_braces_within_strings() {
+      let output = "Progress: {\n    \"State\": \"PAUSED {\",\n    \"Note\": \"\\\"}\"\n}";
+
+      assert_eq!(states(output), vec![EncoderState::Other("PAUSED {".to_owned())])
+    }
+
+    #[test]
+    fn ignores_lines_outside_blocks() {
+      let output = "[12:00:00] hb_init: starting libhb thread\nEncode done!\n";
+
+      assert_eq!(parse_all(output), vec![])
+    }
+
+    #[test]
+    fn reports_malformed_blocks_instead_of_panicking() {
+      let output = "Progress: {\n    \"State\": \"WORKING\",\n    \"Working\": {\"Progress\": \"lots\"}\n}";
+
+      assert!(matches!(&parse_all(output)[..], [Output::Malformed(label, _)] if label == "Progress"))
+    }
+
+    #[test]
+    fn starts_over_when_a_block_is_truncated() {
+      let output = "Progress: {\n    \"State\": \"WORKING\",\nProgress: {\n    \"State\": \"WORKDONE\",\n    \"WorkDone\": {\"Error\": 3}\n}";
+
+      assert_eq!(states(output), vec![EncoderState::WorkDone(WorkDone { error: 3 })])
+    }
+}
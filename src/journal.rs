@@ -0,0 +1,320 @@
+use std::fmt;
+use std::path::{Path, PathBuf};
+use serde::{Deserialize, Serialize};
+use dirs::home_dir;
+
+use crate::error::{DeoJournalError, FileName};
+use crate::handbrake::EncodeJob;
+use crate::models::SessionId;
+
+/// The queue of a run and how far each file has progressed. It is saved to disk before encoding starts and after
+/// every change, so that an interrupted run can be resumed.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Journal {
+  #[serde(skip)]
+  path: PathBuf,
+  entries: Vec<JournalEntry>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JournalEntry {
+  pub job: EncodeJob,
+  pub status: JournalStatus,
+  /// Whether the source has been disposed of with the rest of its session.
+  #[serde(default)]
+  pub disposed: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum JournalStatus {
+  Pending,
+  Encoding,
+  Completed,
+  Failed,
+  VerificationFailed,
+  Skipped,
+  Cancelled,
+}
+
+impl JournalStatus {
+  /// Whether a file with this status needs to be encoded when resuming.
+  pub fn is_unfinished(&self) -> bool {
+    matches!(self, JournalStatus::Pending | JournalStatus::Encoding | JournalStatus::Cancelled)
+  }
+}
+
+impl fmt::Display for JournalStatus {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let status = match self {
+      JournalStatus::Pending => "pending",
+      JournalStatus::Encoding => "encoding",
+      JournalStatus::Completed => "completed",
+      JournalStatus::Failed => "failed",
+      JournalStatus::VerificationFailed => "verification failed",
+      JournalStatus::Skipped => "skipped",
+      JournalStatus::Cancelled => "cancelled",
+    };
+
+    write!(f, "{}", status)
+  }
+}
+
+impl Journal {
+  pub fn new(jobs: Vec<EncodeJob>) -> Result<Self, DeoJournalError> {
+    journal_path()
+      .map(|path| Self::at(path, jobs))
+  }
+
+  fn at(path: PathBuf, jobs: Vec<EncodeJob>) -> Self {
+    let entries =
+      jobs
+        .into_iter()
+        .map(|job| JournalEntry { job, status: JournalStatus::Pending, disposed: false })
+        .collect();
+
+    Self { path, entries }
+  }
+
+  /// Queue more jobs after the existing entries, such as the new sessions found by a watch.
+  pub fn append(&mut self, jobs: Vec<EncodeJob>) {
+    self
+      .entries
+      .extend(jobs.into_iter().map(|job| JournalEntry { job, status: JournalStatus::Pending, disposed: false }))
+  }
+
+  pub fn entries(&self) -> &[JournalEntry] {
+    &self.entries
+  }
+
+  /// Indexes of all entries that still need encoding, in queue order.
+  pub fn unfinished(&self) -> Vec<usize> {
+    self
+      .entries
+      .iter()
+      .enumerate()
+      .filter_map(|(index, entry)| entry.status.is_unfinished().then_some(index))
+      .collect()
+  }
+
+  /// The completed jobs of a session that haven't been disposed of yet, once every file in the session has finished
+  /// without failing. They are marked as disposed of, as a session finishes again when it is resumed or a watch queues
+  /// more of its files.
+  pub fn dispose_successful_session(&mut self, session_id: &SessionId) -> Option<Vec<EncodeJob>> {
+    let mut session_entries: Vec<&mut JournalEntry> =
+      self
+        .entries
+        .iter_mut()
+        .filter(|entry| &entry.job.session_id == session_id)
+        .collect();
+
+    session_entries
+      .iter()
+      .all(|entry| matches!(entry.status, JournalStatus::Completed | JournalStatus::Skipped))
+      .then(|| {
+        session_entries
+          .iter_mut()
+          .filter(|entry| entry.status == JournalStatus::Completed && !entry.disposed)
+          .map(|entry| {
+            entry.disposed = true;
+            entry.job.clone()
+          })
+          .collect()
+      })
+  }
+
+  pub fn update(&mut self, index: usize, status: JournalStatus) -> Result<(), DeoJournalError> {
+    if let Some(entry) = self.entries.get_mut(index) {
+      entry.status = status
+    }
+
+    self.save()
+  }
+
+  /// Write the journal to a temporary file and move it into place, so a crash never leaves a half written journal.
+  pub fn save(&self) -> Result<(), DeoJournalError> {
+    let json =
+      serde_json::to_string_pretty(self)
+        .map_err(|e| DeoJournalError::CouldNotWriteJournal(FileName::new(&self.path), e.to_string()))?;
+
+    let temp_path = self.path.with_extension("json.tmp");
+
+    std::fs::write(&temp_path, json)
+      .and_then(|_| std::fs::rename(&temp_path, &self.path))
+      .map_err(|e| DeoJournalError::CouldNotWriteJournal(FileName::new(&self.path), e.to_string()))
+  }
+}
+
+pub fn read_journal() -> Result<Journal, DeoJournalError> {
+  journal_path()
+    .and_then(read_journal_at)
+}
+
+fn read_journal_at(path: PathBuf) -> Result<Journal, DeoJournalError> {
+  if !path.exists() {
+    Err(DeoJournalError::NoJournalFound(FileName::new(&path)))
+  } else {
+    std::fs::read_to_string(&path)
+      .map_err(|e| DeoJournalError::CouldNotReadJournal(FileName::new(&path), e.to_string()))
+      .and_then(|json| {
+        serde_json::from_str::<Journal>(&json)
+          .map_err(|e| DeoJournalError::CouldNotDecodeJournal(FileName::new(&path), e.to_string()))
+      })
+      .map(|journal| Journal { path, ..journal })
+  }
+}
+
+/// Start an empty journal for a watch, which appends the new sessions it finds to it. The journal of an unfinished run
+/// is never replaced, so that it can still be resumed.
+pub fn start_watch_journal() -> Result<Journal, DeoJournalError> {
+  check_unfinished()?;
+  let journal = Journal::new(vec![])?;
+  journal.save().map(|_| journal)
+}
+
+/// Fails with UnfinishedJournal when the journal has files that `deo resume` would still encode.
+pub fn check_unfinished() -> Result<(), DeoJournalError> {
+  match read_journal() {
+    Ok(journal) if !journal.unfinished().is_empty() =>
+      Err(DeoJournalError::UnfinishedJournal(FileName::new(&journal.path), journal.unfinished().len())),
+    Ok(_) | Err(DeoJournalError::NoJournalFound(_)) => Ok(()),
+    Err(error) => Err(error),
+  }
+}
+
+fn journal_path() -> Result<PathBuf, DeoJournalError> {
+  home_dir()
+    .ok_or(DeoJournalError::CouldNotFindHomeDir)
+    .and_then(|hd| {
+      let deo_dir = hd.join(".deo");
+      create_dir(&deo_dir).map(|_| deo_dir.join("journal.json"))
+    })
+}
+
+fn create_dir(dir: &Path) -> Result<(), DeoJournalError> {
+  std::fs::create_dir_all(dir)
+    .map_err(|e| DeoJournalError::CouldNotWriteJournal(FileName::new(dir), e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use crate::models::InputFile;
+    use crate::profiles::{Backend, Container, ProfileConfigItem};
+    use tempfile::TempDir;
+
+    fn job(session_id: &str, episode: &str) -> EncodeJob {
+      EncodeJob {
+        session_id: SessionId::new(session_id),
+        encode_dir_path: PathBuf::from("/Encodes/ThunderCats {tvdb-70355}/Season 01"),
+        profile: ProfileConfigItem::new("Fast 1080p", Backend::Handbrake, Container::Mp4),
+        fallback_profile: None,
+        input: InputFile { mkv_file: format!("{episode}.mkv"), mkv_path: PathBuf::from(format!("/Rips/{session_id}/renames/{episode}.mkv")) },
+        output_file: format!("{episode}.mp4"),
+      }
+    }
+
+    fn journal_with(statuses: &[(&str, JournalStatus)]) -> Journal {
+      let jobs = statuses.iter().enumerate().map(|(index, (session_id, _))| job(session_id, &format!("S01E0{}", index + 1))).collect();
+      let mut journal = Journal::at(PathBuf::from("/nowhere/journal.json"), jobs);
+
+      for (entry, (_, status)) in journal.entries.iter_mut().zip(statuses) {
+        entry.status = *status
+      }
+
+      journal
+    }
+
+    fn episodes(jobs: Option<Vec<EncodeJob>>) -> Option<Vec<String>> {
+      jobs.map(|jobs| jobs.into_iter().map(|job| job.input.mkv_file).collect())
+    }
+
+    #[test]
+    fn resumes_pending_encoding_and_cancelled_files() {
+      let journal =
+        journal_with(&[
+          ("session1", JournalStatus::Pending),
+          ("session1", JournalStatus::Encoding),
+          ("session1", JournalStatus::Completed),
+          ("session1", JournalStatus::Failed),
+          ("session1", JournalStatus::VerificationFailed),
+          ("session1", JournalStatus::Skipped),
+          ("session1", JournalStatus::Cancelled),
+        ]);
+
+      assert_eq!(journal.unfinished(), vec![0, 1, 6])
+    }
+
+    #[test]
+    fn finishes_a_session_once_every_file_is_completed_or_skipped() {
+      let mut journal =
+        journal_with(&[
+          ("session1", JournalStatus::Completed),
+          ("session1", JournalStatus::Skipped),
+          ("session2", JournalStatus::Failed),
+        ]);
+
+      assert_eq!(episodes(journal.dispose_successful_session(&SessionId::new("session1"))), Some(vec!["S01E01.mkv".to_owned()]));
+      assert_eq!(episodes(journal.dispose_successful_session(&SessionId::new("session2"))), None);
+    }
+
+    #[test]
+    fn does_not_finish_a_session_with_a_file_that_failed_or_was_cancelled() {
+      for status in [JournalStatus::Failed, JournalStatus::VerificationFailed, JournalStatus::Cancelled, JournalStatus::Pending, JournalStatus::Encoding] {
+        let mut journal = journal_with(&[("session1", JournalStatus::Completed), ("session1", status)]);
+
+        assert_eq!(episodes(journal.dispose_successful_session(&SessionId::new("session1"))), None, "with a file that is {}", status)
+      }
+    }
+
+    #[test]
+    fn disposes_of_each_completed_file_once() {
+      let mut journal = journal_with(&[("session1", JournalStatus::Completed), ("session1", JournalStatus::Completed)]);
+      journal.entries[1].disposed = true;
+
+      assert_eq!(episodes(journal.dispose_successful_session(&SessionId::new("session1"))), Some(vec!["S01E01.mkv".to_owned()]));
+      assert_eq!(episodes(journal.dispose_successful_session(&SessionId::new("session1"))), Some(vec![]));
+
+      journal.append(vec![job("session1", "S01E03")]);
+      journal.entries[2].status = JournalStatus::Completed;
+
+      assert_eq!(episodes(journal.dispose_successful_session(&SessionId::new("session1"))), Some(vec!["S01E03.mkv".to_owned()]));
+    }
+
+    #[test]
+    fn saves_each_update() {
+      let dir = TempDir::new().unwrap();
+      let path = dir.path().join("journal.json");
+      let journal = Journal::at(path.clone(), vec![job("session1", "S01E01"), job("session1", "S01E02")]);
+      journal.save().unwrap();
+
+      let mut journal = read_journal_at(path.clone()).unwrap();
+      journal.update(0, JournalStatus::Completed).unwrap();
+      journal.update(1, JournalStatus::Cancelled).unwrap();
+
+      let reloaded = read_journal_at(path.clone()).unwrap();
+      let statuses: Vec<JournalStatus> = reloaded.entries().iter().map(|entry| entry.status).collect();
+
+      assert_eq!(statuses, vec![JournalStatus::Completed, JournalStatus::Cancelled]);
+      assert_eq!(reloaded.entries()[1].job.input.mkv_file, "S01E02.mkv");
+      assert_eq!(reloaded.unfinished(), vec![1]);
+      assert_eq!(reloaded.path, path);
+    }
+
+    #[test]
+    fn appends_pending_jobs() {
+      let mut journal = journal_with(&[("session1", JournalStatus::Completed)]);
+      journal.append(vec![job("session2", "S01E02")]);
+
+      assert_eq!(journal.unfinished(), vec![1]);
+      assert_eq!(journal.entries()[1].job.session_id, SessionId::new("session2"));
+    }
+
+    #[test]
+    fn reports_a_missing_journal() {
+      let dir = TempDir::new().unwrap();
+      let path = dir.path().join("journal.json");
+
+      assert!(matches!(read_journal_at(path.clone()), Err(DeoJournalError::NoJournalFound(_))));
+    }
+}
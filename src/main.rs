@@ -12,6 +12,10 @@ mod debug;
 mod colours;
 mod workflow;
 mod models;
+mod journal;
+mod config;
+mod rules;
+mod watch;
 
 fn main() {
   workflow::perform(cli::get_cli_args())
@@ -1,11 +1,11 @@
 use crate::models::MovieRenameFile;
 use crate::models::TVSeriesRenameFile;
 use std::path::PathBuf;
+use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct InputFile {
   pub mkv_file: String,
-  pub mp4_file: String,
   pub mkv_path: PathBuf,
 }
 
@@ -13,7 +13,6 @@ impl From<TVSeriesRenameFile> for InputFile {
   fn from(tv_rename: TVSeriesRenameFile) -> Self {
       Self {
         mkv_file: tv_rename.mkv_file,
-        mp4_file: tv_rename.mp4_file,
         mkv_path: tv_rename.path,
       }
   }
@@ -23,7 +22,6 @@ impl From<MovieRenameFile> for InputFile {
   fn from(movie_rename: MovieRenameFile) -> Self {
       Self {
         mkv_file: movie_rename.mkv_file,
-        mp4_file: movie_rename.mp4_file,
         mkv_path: movie_rename.path,
       }
   }
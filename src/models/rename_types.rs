@@ -1,5 +1,4 @@
 use std::collections::HashMap;
-use std::path::Path;
 use std::path::PathBuf;
 
 use super::movie::MovieSession;
@@ -28,9 +27,6 @@ pub struct TVSeriesRenameFile {
 
   /// Input file name and ext - file to be encoded
   pub mkv_file: String,
-
-  /// Output file and ext - encoded file
-  pub mp4_file: String,
 }
 
 #[derive(Debug, Clone, Eq, PartialEq)]
@@ -44,9 +40,6 @@ pub struct MovieRenameFile {
 
   /// Input file name and ext - file to be encoded
   pub mkv_file: String,
-
-  /// Output file and ext - encoded file
-  pub mp4_file: String,
 }
 
 /// Convert from a collection of RenameFile into a Map<SessionId, TVSeriesSession>
@@ -95,14 +88,6 @@ impl TryFrom<EntryType> for RenameTypes {
   fn try_from(value: EntryType) -> Result<Self, Self::Error> {
     match value {
       EntryType::TVSeriesRename { path, session, episode, file } => {
-        let output_path = Path::new(&file);
-
-        let mp4_file =
-          output_path
-            .file_stem()
-            .map(|f| format!("{}.mp4", f.to_string_lossy()))
-            .expect("Could not get file stem");
-
         let mkv_file = file;
 
         Ok(
@@ -111,19 +96,10 @@ impl TryFrom<EntryType> for RenameTypes {
             session,
             episode,
             mkv_file,
-            mp4_file,
           }
         ))
       },
       EntryType::MovieRename { path, session, file } => {
-        let output_path = Path::new(&file);
-
-        let mp4_file =
-          output_path
-            .file_stem()
-            .map(|f| format!("{}.mp4", f.to_string_lossy()))
-            .expect("Could not get file stem");
-
         let mkv_file = file;
 
         Ok(
@@ -131,7 +107,6 @@ impl TryFrom<EntryType> for RenameTypes {
             path,
             session,
             mkv_file,
-            mp4_file,
           }
         ))
       },
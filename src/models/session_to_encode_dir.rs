@@ -84,21 +84,6 @@ impl SessionToEncodeDir {
     }
   }
 
-  pub fn file_count(&self) -> u64 {
-    match self.clone() {
-        SessionToEncodeDir::TVSeriesMapping(tvseries_to_encode_dir) =>
-          tvseries_to_encode_dir
-            .session()
-            .files()
-            .len() as u64,
-        SessionToEncodeDir::MovieMapping(movie_to_encode_dir) =>
-          movie_to_encode_dir
-            .session()
-            .files()
-            .len() as u64
-    }
-  }
-
   pub fn session_id(&self) -> SessionId {
     match self {
         SessionToEncodeDir::TVSeriesMapping(tvseries_to_encode_dir) => tvseries_to_encode_dir.session_id(),
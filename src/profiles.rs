@@ -1,15 +1,77 @@
+use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use walkdir::WalkDir;
-use dirs::home_dir;
+use std::path::Path;
 use std::fmt;
 
 use crate::error::{DeoProfileError, DirName, FileName};
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ProfileConfigItem {
   full: String,
   display_name: String,
-  preset_name: String
+  preset_name: String,
+  #[serde(default)]
+  backend: Backend,
+  /// Encoding arguments of an ffmpeg profile
+  #[serde(default)]
+  arguments: Vec<String>,
+  #[serde(default)]
+  container: Container,
+}
+
+/// The encoder a profile is written for. Profiles exported from handbrake don't state a backend.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Backend {
+  #[default]
+  Handbrake,
+  Ffmpeg,
+}
+
+/// The container a profile encodes to, which decides the extension of its outputs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Container {
+  #[default]
+  Mp4,
+  Mkv,
+  Webm,
+}
+
+impl Container {
+  /// The container of a handbrake FileFormat, such as `av_mkv`.
+  fn from_file_format(file_format: &str) -> Option<Self> {
+    match file_format {
+      "av_mp4" => Some(Container::Mp4),
+      "av_mkv" => Some(Container::Mkv),
+      "av_webm" => Some(Container::Webm),
+      _ => None,
+    }
+  }
+
+  /// The container that a file with the extension holds.
+  fn from_extension(extension: &str) -> Option<Self> {
+    match extension.to_ascii_lowercase().as_str() {
+      "mp4" | "m4v" => Some(Container::Mp4),
+      "mkv" => Some(Container::Mkv),
+      "webm" => Some(Container::Webm),
+      _ => None,
+    }
+  }
+
+  pub fn extension(&self) -> &'static str {
+    match self {
+      Container::Mp4 => "mp4",
+      Container::Mkv => "mkv",
+      Container::Webm => "webm",
+    }
+  }
+
+  /// Whether a file with the extension would hold this container, such as an m4v file for mp4.
+  pub fn suits_extension(&self, extension: &str) -> bool {
+    Container::from_extension(extension) == Some(*self)
+  }
 }
 
 #[derive(Debug)]
@@ -33,12 +95,42 @@ impl fmt::Display for ProfileSelection {
 pub struct ProfileConfig(Vec<ProfileConfigItem>);
 
 impl ProfileConfig {
+  #[cfg(test)]
+  pub fn new(items: Vec<ProfileConfigItem>) -> Self {
+    Self(items)
+  }
+
   pub fn items(&self) -> &[ProfileConfigItem] {
     &self.0
   }
+
+  pub fn find(&self, name: &str) -> Option<&ProfileConfigItem> {
+    self.0.iter().find(|p| p.display_name == name)
+  }
+
+  pub fn names(&self) -> Vec<String> {
+    self.0.iter().map(|p| p.display_name.clone()).collect()
+  }
 }
 
 impl ProfileConfigItem {
+  #[cfg(test)]
+  pub fn new(name: &str, backend: Backend, container: Container) -> Self {
+    Self {
+      full: format!("/profiles/{}.json", name),
+      display_name: name.to_owned(),
+      preset_name: name.to_owned(),
+      backend,
+      arguments: vec![],
+      container,
+    }
+  }
+
+  #[cfg(test)]
+  pub fn with_arguments(self, arguments: &[&str]) -> Self {
+    Self { arguments: arguments.iter().map(|argument| argument.to_string()).collect(), ..self }
+  }
+
   pub fn full_path(&self) -> &str {
     self.full.as_str()
   }
@@ -46,6 +138,18 @@ impl ProfileConfigItem {
   pub fn preset_name(&self) -> &str {
     self.preset_name.as_str()
   }
+
+  pub fn backend(&self) -> Backend {
+    self.backend
+  }
+
+  pub fn arguments(&self) -> &[String] {
+    &self.arguments
+  }
+
+  pub fn container(&self) -> Container {
+    self.container
+  }
 }
 
 impl fmt::Display for ProfileConfigItem {
@@ -54,63 +158,186 @@ impl fmt::Display for ProfileConfigItem {
   }
 }
 
-pub fn read_profile_config() -> Result<ProfileConfig, DeoProfileError> {
-
-  home_dir()
-    .ok_or(DeoProfileError::CouldNotFindHomeDir)
-    .and_then(|hd| {
-      let profiles_path = hd.join(".deo").join("profiles");
-      if !(profiles_path.exists() && profiles_path.is_dir()) {
-        Err(DeoProfileError::ProfilesDirDoesNotExist(DirName::new(profiles_path)))
-      } else {
-        let profile_config_items: Result<Vec<ProfileConfigItem>, DeoProfileError> =
-          WalkDir::new(profiles_path.clone())
-            .into_iter()
-            .filter_map(|de| de.ok())
-            .filter_map(|de| {
-              if de.file_type().is_file() && de.path().extension().filter(|ext| &ext.to_string_lossy() == "json").is_some() {
-                let result: Result<ProfileConfigItem, DeoProfileError> =
-                  std::fs::read_to_string(de.path())
-                    .map_err(|e| DeoProfileError::CouldNotReadProfile(FileName::new(de.path()), e.to_string() ))
-                    .and_then(|profile_json| {
-                        serde_json::from_str(&profile_json)
-                          .map_err(|e| DeoProfileError::CouldNotDecodeProfile(FileName::new(de.path()), e.to_string()) )
-                          .and_then(|json: Value| {
-                            let preset_name_value = &json["PresetList"][0]["PresetName"];
-
-                            match preset_name_value {
-                                Value::String(preset_name) => {
-                                  let display_name = de.path().file_stem().map_or_else(|| "<Unknown>".to_owned(), |filestem| filestem.to_string_lossy().to_string());
-                                  let full = de.path().to_string_lossy().to_string();
-                                  Ok(
-                                    ProfileConfigItem {
-                                      full,
-                                      display_name,
-                                      preset_name: preset_name.to_owned(),
-                                    }
-                                  )
-                                },
-                                value => Err(DeoProfileError::ProfilePresetNameIsNotString(FileName::new(de.path()), value.to_string())),
-                            }
-                          })
-                  });
-
-                Some(result)
-              } else {
-                None
-              }
-            })
-            .collect();
-
-
-        profile_config_items
-          .and_then(|profile_items| {
-            if profile_items.is_empty() {
-              Err(DeoProfileError::NoProfilesFound(DirName::new(profiles_path)))
-            } else {
-              Ok(ProfileConfig(profile_items))
+/// Decode a profile. Profiles exported from handbrake are used as is, while ffmpeg profiles state their backend and
+/// the arguments to encode with, eg. `{"Backend": "ffmpeg", "Arguments": ["-c:v", "libx265", "-crf", "22"]}`, and
+/// optionally the extension of their outputs, eg. `"Extension": "mkv"`
+fn profile_config_item(path: &Path, json: &Value) -> Result<ProfileConfigItem, DeoProfileError> {
+  let display_name = path.file_stem().map_or_else(|| "<Unknown>".to_owned(), |filestem| filestem.to_string_lossy().to_string());
+  let full = path.to_string_lossy().to_string();
+
+  match &json["Backend"] {
+    Value::Null => handbrake_profile(path, json, full, display_name),
+    Value::String(backend) if backend.eq_ignore_ascii_case("handbrake") => handbrake_profile(path, json, full, display_name),
+    Value::String(backend) if backend.eq_ignore_ascii_case("ffmpeg") => {
+      let arguments: Option<Vec<String>> =
+        json["Arguments"]
+          .as_array()
+          .and_then(|arguments| {
+            arguments
+              .iter()
+              .map(|argument| argument.as_str().map(str::to_owned))
+              .collect()
+          });
+
+      let container =
+        match &json["Extension"] {
+          Value::Null => Ok(Container::default()),
+          Value::String(extension) => Container::from_extension(extension.trim_start_matches('.')).ok_or(()),
+          _ => Err(()),
+        }
+        .map_err(|_| DeoProfileError::UnknownProfileExtension(FileName::new(path), json["Extension"].to_string()))?;
+
+      match arguments {
+        Some(arguments) => {
+          Ok(
+            ProfileConfigItem {
+              full,
+              preset_name: display_name.clone(),
+              display_name,
+              backend: Backend::Ffmpeg,
+              arguments,
+              container,
             }
-          })
+          )
+        },
+        None => Err(DeoProfileError::ProfileArgumentsAreNotStrings(FileName::new(path), json["Arguments"].to_string())),
+      }
+    },
+    backend => Err(DeoProfileError::UnknownProfileBackend(FileName::new(path), backend.to_string())),
+  }
+}
+
+fn handbrake_profile(path: &Path, json: &Value, full: String, display_name: String) -> Result<ProfileConfigItem, DeoProfileError> {
+  let preset_name_value = &json["PresetList"][0]["PresetName"];
+  let file_format_value = &json["PresetList"][0]["FileFormat"];
+
+  // Handbrake encodes to mp4 when a preset doesn't have a FileFormat
+  let container =
+    match file_format_value {
+      Value::Null => Ok(Container::default()),
+      Value::String(file_format) => Container::from_file_format(file_format).ok_or(()),
+      _ => Err(()),
+    }
+    .map_err(|_| DeoProfileError::UnknownProfileFileFormat(FileName::new(path), file_format_value.to_string()))?;
+
+  match preset_name_value {
+    Value::String(preset_name) => {
+      Ok(
+        ProfileConfigItem {
+          full,
+          display_name,
+          preset_name: preset_name.to_owned(),
+          backend: Backend::Handbrake,
+          arguments: vec![],
+          container,
         }
-    })
+      )
+    },
+    value => Err(DeoProfileError::ProfilePresetNameIsNotString(FileName::new(path), value.to_string())),
+  }
+}
+
+pub fn read_profile_config(profiles_path: &Path) -> Result<ProfileConfig, DeoProfileError> {
+  let profiles_path = profiles_path.to_owned();
+  if !(profiles_path.exists() && profiles_path.is_dir()) {
+    Err(DeoProfileError::ProfilesDirDoesNotExist(DirName::new(profiles_path)))
+  } else {
+    let profile_config_items: Result<Vec<ProfileConfigItem>, DeoProfileError> =
+      WalkDir::new(profiles_path.clone())
+        .into_iter()
+        .filter_map(|de| de.ok())
+        .filter_map(|de| {
+          if de.file_type().is_file() && de.path().extension().filter(|ext| &ext.to_string_lossy() == "json").is_some() {
+            let result: Result<ProfileConfigItem, DeoProfileError> =
+              std::fs::read_to_string(de.path())
+                .map_err(|e| DeoProfileError::CouldNotReadProfile(FileName::new(de.path()), e.to_string() ))
+                .and_then(|profile_json| {
+                    serde_json::from_str(&profile_json)
+                      .map_err(|e| DeoProfileError::CouldNotDecodeProfile(FileName::new(de.path()), e.to_string()) )
+                      .and_then(|json: Value| profile_config_item(de.path(), &json))
+              });
+
+            Some(result)
+          } else {
+            None
+          }
+        })
+        .collect();
+
+
+    profile_config_items
+      .and_then(|profile_items| {
+        if profile_items.is_empty() {
+          Err(DeoProfileError::NoProfilesFound(DirName::new(profiles_path)))
+        } else {
+          Ok(ProfileConfig(profile_items))
+        }
+      })
+  }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn decode(json: &str) -> Result<ProfileConfigItem, DeoProfileError> {
+      profile_config_item(Path::new("/profiles/Fast 1080p.json"), &serde_json::from_str(json).unwrap())
+    }
+
+    #[test]
+    fn decodes_handbrake_profiles() {
+      let profile = decode(r#"{"PresetList": [{"PresetName": "Fast 1080p30"}]}"#).unwrap();
+
+      assert_eq!(profile.to_string(), "Fast 1080p");
+      assert_eq!(profile.preset_name(), "Fast 1080p30");
+      assert_eq!(profile.backend(), Backend::Handbrake);
+    }
+
+    #[test]
+    fn decodes_ffmpeg_profiles() {
+      let profile = decode(r#"{"Backend": "ffmpeg", "Arguments": ["-c:v", "libx265", "-crf", "22"]}"#).unwrap();
+
+      assert_eq!(profile.backend(), Backend::Ffmpeg);
+      assert_eq!(profile.arguments(), ["-c:v", "libx265", "-crf", "22"]);
+    }
+
+    #[test]
+    fn rejects_ffmpeg_profiles_without_string_arguments() {
+      assert!(matches!(decode(r#"{"Backend": "ffmpeg", "Arguments": ["-crf", 22]}"#), Err(DeoProfileError::ProfileArgumentsAreNotStrings(..))));
+      assert!(matches!(decode(r#"{"Backend": "ffmpeg"}"#), Err(DeoProfileError::ProfileArgumentsAreNotStrings(..))));
+    }
+
+    #[test]
+    fn takes_the_container_from_the_file_format_of_handbrake_profiles() {
+      let container = |file_format: &str| decode(&format!(r#"{{"PresetList": [{{"PresetName": "Fast 1080p30"{}}}]}}"#, file_format)).map(|profile| profile.container());
+
+      assert_eq!(container("").unwrap(), Container::Mp4);
+      assert_eq!(container(r#", "FileFormat": "av_mp4""#).unwrap(), Container::Mp4);
+      assert_eq!(container(r#", "FileFormat": "av_mkv""#).unwrap(), Container::Mkv);
+      assert_eq!(container(r#", "FileFormat": "av_webm""#).unwrap(), Container::Webm);
+      assert!(matches!(container(r#", "FileFormat": "av_avi""#), Err(DeoProfileError::UnknownProfileFileFormat(..))));
+    }
+
+    #[test]
+    fn takes_the_container_from_the_extension_of_ffmpeg_profiles() {
+      let container = |extension: &str| decode(&format!(r#"{{"Backend": "ffmpeg", "Arguments": []{}}}"#, extension)).map(|profile| profile.container());
+
+      assert_eq!(container("").unwrap(), Container::Mp4);
+      assert_eq!(container(r#", "Extension": "mkv""#).unwrap(), Container::Mkv);
+      assert_eq!(container(r#", "Extension": ".webm""#).unwrap(), Container::Webm);
+      assert!(matches!(container(r#", "Extension": "avi""#), Err(DeoProfileError::UnknownProfileExtension(..))));
+    }
+
+    #[test]
+    fn suits_extensions_of_the_same_container() {
+      assert!(Container::Mp4.suits_extension("m4v"));
+      assert!(Container::Mkv.suits_extension("MKV"));
+      assert!(!Container::Mkv.suits_extension("mp4"));
+    }
+
+    #[test]
+    fn rejects_unknown_backends() {
+      assert!(matches!(decode(r#"{"Backend": "vlc"}"#), Err(DeoProfileError::UnknownProfileBackend(..))))
+    }
 }
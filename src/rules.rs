@@ -0,0 +1,388 @@
+use std::fmt;
+use std::path::Path;
+use std::sync::LazyLock;
+
+use console::style;
+use regex::Regex;
+use serde::Deserialize;
+
+use crate::debug;
+use crate::error::{DeoRulesError, FileName};
+use crate::models::SessionToEncodeDir;
+use crate::profiles::{ProfileConfig, ProfileConfigItem};
+
+static TVDB_ID_REG: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^(.*?)(?:\s+-)?\s*\{tvdb-(\d+)\}").unwrap());
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ContentType {
+  Tv,
+  Movie,
+}
+
+impl fmt::Display for ContentType {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      ContentType::Tv => write!(f, "tv"),
+      ContentType::Movie => write!(f, "movie"),
+    }
+  }
+}
+
+/// The rules file, eg:
+///
+/// ```toml
+/// [[rules]]
+/// series = "ThunderCats"
+/// profile = "Fast 1080p"
+/// ```
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RulesFile {
+  #[serde(default)]
+  rules: Vec<RuleConfig>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RuleConfig {
+  name: Option<String>,
+  series: Option<String>,
+  movie: Option<String>,
+  tvdb: Option<String>,
+  #[serde(rename = "type")]
+  content_type: Option<ContentType>,
+  session: Option<String>,
+  profile: String,
+  #[serde(default)]
+  apply: bool,
+}
+
+/// A profile for the sessions that match every pattern of the rule. Each pattern must match the whole of its value.
+#[derive(Debug, Clone)]
+pub struct Rule {
+  name: String,
+  series: Option<Regex>,
+  movie: Option<Regex>,
+  tvdb: Option<Regex>,
+  content_type: Option<ContentType>,
+  session: Option<Regex>,
+  profile: ProfileConfigItem,
+  /// Whether to encode with the profile without asking, rather than choosing it by default
+  apply: bool,
+}
+
+/// What the rules can match a session on.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SessionDetails {
+  pub session_id: String,
+  pub content_type: ContentType,
+  /// The name of the series or movie, without its tvdb id
+  pub title: String,
+  pub tvdb_id: Option<String>,
+}
+
+#[derive(Debug)]
+pub enum RuleMatch<'a> {
+  NoMatch,
+  Matched(&'a Rule),
+  /// Rules that matched with different profiles
+  Conflict(Vec<&'a Rule>),
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ProfileRules(Vec<Rule>);
+
+impl Rule {
+  pub fn name(&self) -> &str {
+    &self.name
+  }
+
+  pub fn profile(&self) -> &ProfileConfigItem {
+    &self.profile
+  }
+
+  pub fn apply(&self) -> bool {
+    self.apply
+  }
+
+  fn matches(&self, details: &SessionDetails) -> bool {
+    let matches_pattern = |pattern: &Option<Regex>, value: Option<&str>| {
+      pattern
+        .as_ref()
+        .is_none_or(|pattern| value.is_some_and(|value| pattern.is_match(value)))
+    };
+
+    let tv_title = (details.content_type == ContentType::Tv).then_some(details.title.as_str());
+    let movie_title = (details.content_type == ContentType::Movie).then_some(details.title.as_str());
+
+    matches_pattern(&self.series, tv_title) &&
+      matches_pattern(&self.movie, movie_title) &&
+      matches_pattern(&self.tvdb, details.tvdb_id.as_deref()) &&
+      matches_pattern(&self.session, Some(&details.session_id)) &&
+      self.content_type.is_none_or(|content_type| content_type == details.content_type)
+  }
+}
+
+impl fmt::Display for Rule {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let pattern = |field: &str, pattern: &Option<Regex>| pattern.as_ref().map(|pattern| format!("{} = {}", field, unanchored(pattern)));
+
+    let patterns: Vec<String> =
+      [
+        pattern("series", &self.series),
+        pattern("movie", &self.movie),
+        pattern("tvdb", &self.tvdb),
+        self.content_type.map(|content_type| format!("type = {}", content_type)),
+        pattern("session", &self.session),
+      ]
+      .into_iter()
+      .flatten()
+      .collect();
+
+    write!(f, "{} ({}) -> {}", self.name, patterns.join(", "), self.profile)
+  }
+}
+
+impl SessionDetails {
+  pub fn new(session_to_encode_dir: &SessionToEncodeDir) -> Self {
+    let (content_type, name) =
+      match session_to_encode_dir {
+        SessionToEncodeDir::TVSeriesMapping(mapping) => {
+          // The season of a series is its directory and season, such as ThunderCats {tvdb-70355}/Season 01
+          let season = mapping.encode_dir().season;
+          let series = season.split('/').next().unwrap_or_default().to_owned();
+          (ContentType::Tv, series)
+        },
+        SessionToEncodeDir::MovieMapping(mapping) => (ContentType::Movie, mapping.encode_dir().movie_name.name()),
+      };
+
+    let (title, tvdb_id) =
+      match TVDB_ID_REG.captures(&name) {
+        Some(captures) => (captures[1].trim().to_owned(), Some(captures[2].to_owned())),
+        None => (name.trim().to_owned(), None),
+      };
+
+    Self {
+      session_id: session_to_encode_dir.session_id().id().to_owned(),
+      content_type,
+      title,
+      tvdb_id,
+    }
+  }
+}
+
+impl ProfileRules {
+  pub fn is_empty(&self) -> bool {
+    self.0.is_empty()
+  }
+
+  /// The rule that matches the session. Rules that match with the same profile don't conflict, and the first of them
+  /// is used.
+  pub fn find(&self, details: &SessionDetails) -> RuleMatch<'_> {
+    let matched: Vec<&Rule> = self.0.iter().filter(|rule| rule.matches(details)).collect();
+
+    match matched.first() {
+      None => RuleMatch::NoMatch,
+      Some(first) if matched.iter().all(|rule| rule.profile.to_string() == first.profile.to_string()) => RuleMatch::Matched(first),
+      Some(_) => RuleMatch::Conflict(matched),
+    }
+  }
+}
+
+/// The rule that chooses the profile of a session. Rules that conflict are reported, and none of them are used.
+pub fn rule_for_session<'a>(rules: &'a ProfileRules, session_to_encode_dir: &SessionToEncodeDir, verbose: bool) -> Option<&'a Rule> {
+  let details = SessionDetails::new(session_to_encode_dir);
+  let rule_match = rules.find(&details);
+  debug::dump_rule_match(&details, &rule_match, verbose && !rules.is_empty());
+
+  match rule_match {
+    RuleMatch::NoMatch => None,
+    RuleMatch::Matched(rule) => Some(rule),
+    RuleMatch::Conflict(conflicting_rules) => {
+      println!("{}", style(format!("Rules for {} ({}) choose different profiles, so none of them are used:", details.session_id, details.title)).yellow());
+      for rule in conflicting_rules {
+        println!("{}", style(format!("  {}", rule)).yellow());
+      }
+      None
+    },
+  }
+}
+
+/// Read the rules file, which is optional unless it has been supplied in the config file.
+pub fn read_rules(rules_file: &Path, required: bool, profiles: &ProfileConfig) -> Result<ProfileRules, DeoRulesError> {
+  if !rules_file.exists() && !required {
+    return Ok(ProfileRules::default())
+  }
+
+  let rules_toml =
+    std::fs::read_to_string(rules_file)
+      .map_err(|e| DeoRulesError::CouldNotReadRules(FileName::new(rules_file), e.to_string()))?;
+
+  decode_rules(rules_file, &rules_toml, profiles)
+}
+
+fn decode_rules(rules_file: &Path, rules_toml: &str, profiles: &ProfileConfig) -> Result<ProfileRules, DeoRulesError> {
+  toml::from_str::<RulesFile>(rules_toml)
+    .map_err(|e| DeoRulesError::CouldNotDecodeRules(FileName::new(rules_file), e.to_string()))?
+    .rules
+    .into_iter()
+    .enumerate()
+    .map(|(index, rule)| {
+      let name = rule.name.clone().unwrap_or_else(|| format!("rule {}", index + 1));
+      let pattern = |pattern: &Option<String>| {
+        pattern
+          .as_ref()
+          .map(|pattern| Regex::new(&format!("^(?:{})$", pattern)))
+          .transpose()
+          .map_err(|e| DeoRulesError::InvalidRulePattern(name.clone(), e.to_string()))
+      };
+
+      if [&rule.series, &rule.movie, &rule.tvdb, &rule.session].iter().all(|pattern| pattern.is_none()) && rule.content_type.is_none() {
+        return Err(DeoRulesError::RuleWithoutPatterns(name))
+      }
+
+      let profile =
+        profiles
+          .find(&rule.profile)
+          .cloned()
+          .ok_or_else(|| DeoRulesError::UnknownRuleProfile(name.clone(), rule.profile.clone(), profiles.names()))?;
+
+      Ok(
+        Rule {
+          series: pattern(&rule.series)?,
+          movie: pattern(&rule.movie)?,
+          tvdb: pattern(&rule.tvdb)?,
+          content_type: rule.content_type,
+          session: pattern(&rule.session)?,
+          profile,
+          apply: rule.apply,
+          name,
+        }
+      )
+    })
+    .collect::<Result<Vec<_>, _>>()
+    .map(ProfileRules)
+}
+
+/// The pattern as it was written in the rules file.
+fn unanchored(pattern: &Regex) -> &str {
+  pattern
+    .as_str()
+    .strip_prefix("^(?:")
+    .and_then(|pattern| pattern.strip_suffix(")$"))
+    .unwrap_or(pattern.as_str())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use crate::profiles::{Backend, Container};
+
+    fn profiles() -> ProfileConfig {
+      let profile = |name: &str| ProfileConfigItem::new(name, Backend::Handbrake, Container::Mp4);
+      ProfileConfig::new(vec![profile("Fast 1080p"), profile("HQ 1080p"), profile("Animation")])
+    }
+
+    fn tv_session(session_id: &str, title: &str, tvdb_id: &str) -> SessionDetails {
+      SessionDetails { session_id: session_id.to_owned(), content_type: ContentType::Tv, title: title.to_owned(), tvdb_id: Some(tvdb_id.to_owned()) }
+    }
+
+    fn matched_profile(rules: &ProfileRules, details: &SessionDetails) -> Option<String> {
+      match rules.find(details) {
+        RuleMatch::Matched(rule) => Some(rule.profile().to_string()),
+        _ => None,
+      }
+    }
+
+    #[test]
+    fn matches_rules_on_every_pattern() {
+      let rules =
+        decode_rules(
+          Path::new("rules.toml"),
+          r#"
+            [[rules]]
+            series = "ThunderCats"
+            session = "session[12]"
+            profile = "Animation"
+
+            [[rules]]
+            tvdb = "70355"
+            profile = "Fast 1080p"
+            apply = true
+
+            [[rules]]
+            type = "movie"
+            profile = "HQ 1080p"
+          "#,
+          &profiles()
+        ).unwrap();
+
+      assert_eq!(matched_profile(&rules, &tv_session("session3", "ThunderCats", "70355")), Some("Fast 1080p".to_owned()));
+      assert_eq!(matched_profile(&rules, &tv_session("session3", "Monk", "78490")), None);
+
+      let movie = SessionDetails { session_id: "session5".to_owned(), content_type: ContentType::Movie, title: "Star Wars".to_owned(), tvdb_id: Some("71".to_owned()) };
+      assert_eq!(matched_profile(&rules, &movie), Some("HQ 1080p".to_owned()));
+    }
+
+    #[test]
+    fn matches_the_whole_value() {
+      let rules = decode_rules(Path::new("rules.toml"), "[[rules]]\nseries = \"Thunder\"\nprofile = \"Animation\"", &profiles()).unwrap();
+
+      assert_eq!(matched_profile(&rules, &tv_session("session1", "ThunderCats", "70355")), None);
+    }
+
+    #[test]
+    fn reports_rules_that_match_with_different_profiles() {
+      let rules =
+        decode_rules(
+          Path::new("rules.toml"),
+          r#"
+            [[rules]]
+            name = "cartoons"
+            series = "ThunderCats|He-Man"
+            profile = "Animation"
+
+            [[rules]]
+            name = "thundercats"
+            tvdb = "70355"
+            profile = "Fast 1080p"
+          "#,
+          &profiles()
+        ).unwrap();
+
+      let conflicting_rules =
+        match rules.find(&tv_session("session1", "ThunderCats", "70355")) {
+          RuleMatch::Conflict(rules) => rules.iter().map(|rule| rule.to_string()).collect(),
+          _ => vec![],
+        };
+
+      assert_eq!(
+        conflicting_rules,
+        vec!["cartoons (series = ThunderCats|He-Man) -> Animation", "thundercats (tvdb = 70355) -> Fast 1080p"]
+      )
+    }
+
+    #[test]
+    fn rejects_rules_with_unknown_profiles() {
+      let result = decode_rules(Path::new("rules.toml"), "[[rules]]\nseries = \"Monk\"\nprofile = \"Slow 4K\"", &profiles());
+
+      assert!(matches!(result, Err(DeoRulesError::UnknownRuleProfile(..))))
+    }
+
+    #[test]
+    fn rejects_rules_without_patterns() {
+      let result = decode_rules(Path::new("rules.toml"), "[[rules]]\nprofile = \"Fast 1080p\"", &profiles());
+
+      assert!(matches!(result, Err(DeoRulesError::RuleWithoutPatterns(..))))
+    }
+
+    #[test]
+    fn takes_the_title_and_tvdb_id_from_the_encode_dir() {
+      let captures = |name: &str| TVDB_ID_REG.captures(name).map(|captures| (captures[1].to_owned(), captures[2].to_owned()));
+
+      assert_eq!(captures("ThunderCats {tvdb-70355}"), Some(("ThunderCats".to_owned(), "70355".to_owned())));
+      assert_eq!(captures("Star Wars - {tvdb-71}"), Some(("Star Wars".to_owned(), "71".to_owned())));
+    }
+}
@@ -1,9 +1,11 @@
 use console::style;
 
-use dialoguer::{theme::ColorfulTheme, FuzzySelect};
-use crate::profiles::{ProfileConfig, ProfileSelection};
-use crate::user_selection::{ContinueType, UserSelection};
-use crate::models::SessionToEncodeDir;
+use dialoguer::{theme::ColorfulTheme, FuzzySelect, MultiSelect};
+use crate::error::DeoSelectionError;
+use crate::profiles::{ProfileConfig, ProfileConfigItem, ProfileSelection};
+use crate::user_selection::{ContinueType, SelectedFile, UserSelection};
+use crate::models::{InputFile, SessionToEncodeDir};
+use crate::rules::{self, ProfileRules};
 
 #[allow(clippy::enum_variant_names)]
 pub enum Interaction {
@@ -11,10 +13,36 @@ pub enum Interaction {
   NoFilesToEncode,
   CancelEncode,
   InteractionError(String),
+  InvalidSelection(DeoSelectionError),
 }
 
-pub fn interact_with_user(sessions_to_encode_dir: Vec<SessionToEncodeDir>, profiles: ProfileConfig) -> Interaction {
-    let selections = get_user_selection(sessions_to_encode_dir, profiles);
+/// Choices supplied on the command line that replace the matching prompts.
+pub struct BatchChoices {
+  pub profile: Option<String>,
+  pub sessions: Vec<String>,
+  pub skip_confirmation: bool,
+}
+
+pub fn interact_with_user(sessions_to_encode_dir: Vec<SessionToEncodeDir>, profiles: ProfileConfig, rules: &ProfileRules, batch: BatchChoices, verbose: bool) -> Interaction {
+    let selections_result =
+      filter_sessions(sessions_to_encode_dir, &batch.sessions)
+        .and_then(|sessions| {
+          match &batch.profile {
+            Some(profile_name) => {
+              profiles
+                .find(profile_name)
+                .map(|profile| Ok(get_batch_selection(sessions, profile)))
+                .ok_or_else(|| DeoSelectionError::UnknownProfile(profile_name.to_owned(), profiles.names()))
+            },
+            None => Ok(get_user_selection(sessions, profiles, rules, verbose))
+          }
+        });
+
+    let selections = match selections_result {
+      Ok(Ok(selections)) => selections,
+      Ok(Err(error)) => return Interaction::InteractionError(error),
+      Err(error) => return Interaction::InvalidSelection(error),
+    };
 
     if selections.is_empty() {
       println!("You made no choices");
@@ -25,6 +53,10 @@ pub fn interact_with_user(sessions_to_encode_dir: Vec<SessionToEncodeDir>, profi
         println!("  {}", selection);
       }
 
+      if batch.skip_confirmation {
+        return Interaction::ProceedToEncode(selections)
+      }
+
       let continue_options =
         [
           ContinueType::EncodeSelection,
@@ -43,7 +75,41 @@ pub fn interact_with_user(sessions_to_encode_dir: Vec<SessionToEncodeDir>, profi
 }
 
 
-fn get_user_selection(sessions_to_encode_dir: Vec<SessionToEncodeDir>, profiles: ProfileConfig) -> Vec<UserSelection> {
+/// Keep only the requested sessions. When no sessions are requested, all sessions are kept.
+pub fn filter_sessions(sessions_to_encode_dir: Vec<SessionToEncodeDir>, requested_sessions: &[String]) -> Result<Vec<SessionToEncodeDir>, DeoSelectionError> {
+  if requested_sessions.is_empty() {
+    Ok(sessions_to_encode_dir)
+  } else {
+    let available_sessions: Vec<String> =
+      sessions_to_encode_dir
+        .iter()
+        .map(|sed| sed.session_id().id().to_owned())
+        .collect();
+
+    match requested_sessions.iter().find(|session| !available_sessions.contains(session)) {
+      Some(unknown_session) => Err(DeoSelectionError::UnknownSession(unknown_session.to_owned(), available_sessions)),
+      None => {
+        Ok(
+          sessions_to_encode_dir
+            .into_iter()
+            .filter(|sed| requested_sessions.iter().any(|session| session == sed.session_id().id()))
+            .collect()
+        )
+      }
+    }
+  }
+}
+
+fn get_batch_selection(sessions_to_encode_dir: Vec<SessionToEncodeDir>, profile: &ProfileConfigItem) -> Vec<UserSelection> {
+  sessions_to_encode_dir
+    .into_iter()
+    .map(|sed| UserSelection::all_files(sed.session_id().clone(), sed, profile.clone()))
+    .collect()
+}
+
+/// Prompt for the files and profile of each session. Fails when a prompt can't be shown, such as without a terminal,
+/// or is cancelled with Esc.
+fn get_user_selection(sessions_to_encode_dir: Vec<SessionToEncodeDir>, profiles: ProfileConfig, rules: &ProfileRules, verbose: bool) -> Result<Vec<UserSelection>, String> {
   let mut profile_options: Vec<ProfileSelection> =
     profiles
       .items()
@@ -61,14 +127,37 @@ fn get_user_selection(sessions_to_encode_dir: Vec<SessionToEncodeDir>, profiles:
     let num = files.len();
     let location = &sed.location();
     println!("{} ({}) has the following {} files:", style(location).underlined(), style(sed.session_id().id()).yellow().bold(), num);
-    for file in files {
-      println!(" - {}", file.mkv_file);
+
+    let file_names: Vec<&str> = files.iter().map(|file| file.mkv_file.as_str()).collect();
+    let selected_files = chosen_files(&files, show_multi_select(&file_names, "Select files to encode:", true)?);
+
+    if selected_files.is_empty() {
+      println!("No files selected, skipping {}", sed.session_id().id());
+      println!();
+      continue
     }
 
-    let selected_profile = show_select(&profile_options, "Select encoding profile:").unwrap();
+    let rule = rules::rule_for_session(rules, &sed, verbose);
+
+    // A rule that applies its profile replaces the profile prompts, while other rules choose the profile by default
+    if let Some(rule) = rule.filter(|rule| rule.apply()) {
+      println!("Encoding with {} from rule: {}", style(rule.profile()).blue(), rule.name());
+      let files = selected_files.into_iter().map(|input| SelectedFile { input, profile: rule.profile().clone() }).collect();
+      selections.push(UserSelection::new(sed.session_id().clone(), sed, rule.profile().clone(), files));
+      println!();
+      continue
+    }
+
+    let default_profile =
+      rule
+        .and_then(|rule| profile_options.iter().position(|option| option.to_string() == rule.profile().to_string()))
+        .unwrap_or(0);
+
+    let selected_profile = show_select_with_default(&profile_options, "Select encoding profile:", default_profile)?;
     match selected_profile {
       ProfileSelection::Select(selected_profile) => {
-        selections.push(UserSelection::new(sed.session_id().clone(), sed, selected_profile.clone()));
+        let files = choose_file_profiles(selected_files, selected_profile, profiles.items())?;
+        selections.push(UserSelection::new(sed.session_id().clone(), sed, selected_profile.clone(), files));
       },
       ProfileSelection::Skip => (),
     }
@@ -76,13 +165,82 @@ fn get_user_selection(sessions_to_encode_dir: Vec<SessionToEncodeDir>, profiles:
     println!()
   }
 
-  selections
+  Ok(selections)
+}
+
+/// The files at the chosen indexes, in the order of the session.
+fn chosen_files(files: &[InputFile], chosen_indexes: Vec<usize>) -> Vec<InputFile> {
+  chosen_indexes
+    .into_iter()
+    .filter_map(|index| files.get(index).cloned())
+    .collect()
+}
+
+/// Encode every file with the profile of its session, except for the files the user chooses another profile for, such
+/// as a double length finale.
+fn choose_file_profiles(input_files: Vec<InputFile>, session_profile: &ProfileConfigItem, profiles: &[ProfileConfigItem]) -> Result<Vec<SelectedFile>, String> {
+  let files: Vec<SelectedFile> =
+    input_files
+      .into_iter()
+      .map(|input| SelectedFile { input, profile: session_profile.clone() })
+      .collect();
+
+  // There is nothing to choose between with a single file or a single profile
+  if files.len() < 2 || profiles.len() < 2 {
+    return Ok(files)
+  }
+
+  let file_names: Vec<String> = files.iter().map(|file| file.input.mkv_file.clone()).collect();
+  let overridden_files = show_multi_select(&file_names, "Select files to encode with a different profile:", false)?;
+
+  let overrides =
+    overridden_files
+      .into_iter()
+      .filter_map(|index| files.get(index).map(|file| (index, file)))
+      .map(|(index, file)| {
+        let prompt = format!("Select encoding profile for {}:", file.input.mkv_file);
+        show_select(profiles, &prompt).map(|profile| (index, profile.clone()))
+      })
+      .collect::<Result<Vec<_>, String>>()?;
+
+  Ok(override_profiles(files, overrides))
+}
+
+/// Give each file at an index its own profile.
+fn override_profiles(mut files: Vec<SelectedFile>, overrides: Vec<(usize, ProfileConfigItem)>) -> Vec<SelectedFile> {
+  for (index, profile) in overrides {
+    if let Some(file) = files.get_mut(index) {
+      file.profile = profile
+    }
+  }
+
+  files
+}
+
+/// Ask a yes or no question, answered no by default.
+pub fn confirm(prompt: &str) -> Result<bool, String> {
+    let options = ["No", "Yes"];
+    show_select(&options, prompt).map(|answer| *answer == "Yes")
+}
+
+/// Returns the indexes of the chosen options.
+fn show_multi_select<T: ToString>(options: &[T], prompt: &str, chosen_by_default: bool) -> Result<Vec<usize>, String> {
+    MultiSelect::with_theme(&ColorfulTheme::default())
+      .with_prompt(prompt)
+      .items(options)
+      .defaults(&vec![chosen_by_default; options.len()])
+      .interact()
+      .map_err(|e| e.to_string())
 }
 
 fn show_select<'a, T: ToString>(options: &'a [T], prompt: &str) -> Result<&'a T, String> {
+    show_select_with_default(options, prompt, 0)
+}
+
+fn show_select_with_default<'a, T: ToString>(options: &'a [T], prompt: &str, default: usize) -> Result<&'a T, String> {
     FuzzySelect::with_theme(&ColorfulTheme::default())
       .with_prompt(prompt)
-      .default(0)
+      .default(default)
       .items(options)
       .interact()
       .map_err(|e| e.to_string())
@@ -92,3 +250,113 @@ fn show_select<'a, T: ToString>(options: &'a [T], prompt: &str) -> Result<&'a T,
           .ok_or_else(|| "Invalid selection index".to_owned())
       })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use std::path::PathBuf;
+    use crate::models::{SessionId, TVSeriesEncodeDir, TVSeriesRenameFile, TVSeriesSession};
+    use crate::profiles::{Backend, Container};
+
+    fn session(session_id: &str, episodes: &[&str]) -> SessionToEncodeDir {
+      let session_id = SessionId::new(session_id);
+      let files =
+        episodes
+          .iter()
+          .map(|episode| {
+            TVSeriesRenameFile {
+              path: PathBuf::from(format!("/Rips/{}/renames/{}.mkv", session_id.id(), episode)),
+              session: session_id.clone(),
+              episode: episode.split(' ').next().unwrap_or_default().to_owned(),
+              mkv_file: format!("{}.mkv", episode),
+            }
+          })
+          .collect();
+
+      let encode_dir =
+        TVSeriesEncodeDir {
+          path: PathBuf::from("/Encodes/ThunderCats {tvdb-70355}/Season 01"),
+          season: "ThunderCats {tvdb-70355}/Season 01".to_owned(),
+          session_id: session_id.clone(),
+        };
+
+      SessionToEncodeDir::new_tv_series_encode_dir(session_id.clone(), TVSeriesSession::new(session_id, files), encode_dir)
+    }
+
+    fn sessions() -> Vec<SessionToEncodeDir> {
+      vec![
+        session("session1", &["S01E01 - Exodus", "S01E02 - The Unholy Alliance"]),
+        session("session2", &["S01E03 - Pumm-Ra"]),
+        session("session3", &["S01E04 - The Terror of Hammerhand"]),
+      ]
+    }
+
+    fn session_ids(sessions: Vec<SessionToEncodeDir>) -> Vec<String> {
+      sessions.into_iter().map(|sed| sed.session_id().id().to_owned()).collect()
+    }
+
+    #[test]
+    fn keeps_every_session_without_a_filter() {
+      assert_eq!(session_ids(filter_sessions(sessions(), &[]).unwrap()), vec!["session1", "session2", "session3"])
+    }
+
+    #[test]
+    fn keeps_only_the_requested_sessions() {
+      let requested = ["session3".to_owned(), "session1".to_owned()];
+
+      assert_eq!(session_ids(filter_sessions(sessions(), &requested).unwrap()), vec!["session1", "session3"])
+    }
+
+    fn profile(name: &str) -> ProfileConfigItem {
+      ProfileConfigItem::new(name, Backend::Handbrake, Container::Mp4)
+    }
+
+    fn file_profiles(files: Vec<SelectedFile>) -> Vec<(String, String)> {
+      files.into_iter().map(|file| (file.input.mkv_file, file.profile.to_string())).collect()
+    }
+
+    #[test]
+    fn selects_the_chosen_files() {
+      let files = session("session1", &["S01E01 - Exodus", "S01E02 - The Unholy Alliance", "S01E03 - Berbils"]).rename_files();
+      let chosen: Vec<String> = chosen_files(&files, vec![0, 2, 7]).into_iter().map(|file| file.mkv_file).collect();
+
+      assert_eq!(chosen, vec!["S01E01 - Exodus.mkv", "S01E03 - Berbils.mkv"])
+    }
+
+    #[test]
+    fn encodes_a_single_file_with_the_session_profile_without_asking() {
+      let files = session("session2", &["S01E03 - Pumm-Ra"]).rename_files();
+      let selected_files = choose_file_profiles(files, &profile("Fast 1080p"), &[profile("Fast 1080p"), profile("HQ 1080p")]).unwrap();
+
+      assert_eq!(file_profiles(selected_files), vec![("S01E03 - Pumm-Ra.mkv".to_owned(), "Fast 1080p".to_owned())])
+    }
+
+    #[test]
+    fn encodes_overridden_files_with_their_own_profile() {
+      let files: Vec<SelectedFile> =
+        session("session1", &["S01E01 - Exodus", "S01E02 - The Unholy Alliance"])
+          .rename_files()
+          .into_iter()
+          .map(|input| SelectedFile { input, profile: profile("Fast 1080p") })
+          .collect();
+
+      assert_eq!(
+        file_profiles(override_profiles(files, vec![(1, profile("HQ 1080p")), (5, profile("Animation"))])),
+        vec![
+          ("S01E01 - Exodus.mkv".to_owned(), "Fast 1080p".to_owned()),
+          ("S01E02 - The Unholy Alliance.mkv".to_owned(), "HQ 1080p".to_owned()),
+        ]
+      )
+    }
+
+    #[test]
+    fn rejects_an_unknown_session() {
+      let requested = ["session1".to_owned(), "session9".to_owned()];
+
+      assert_eq!(
+        filter_sessions(sessions(), &requested).map(session_ids).unwrap_err().to_string(),
+        "Unknown session: session9, available sessions are: session1, session2, session3"
+      )
+    }
+}
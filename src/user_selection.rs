@@ -3,43 +3,77 @@ use std::path::PathBuf;
 use console::style;
 
 use crate::profiles::ProfileConfigItem;
+use crate::models::InputFile;
 use crate::models::SessionId;
 use crate::models::SessionToEncodeDir;
 
+/// A file to encode, with the profile to encode it with.
+#[derive(Debug, Clone)]
+pub struct SelectedFile {
+  pub input: InputFile,
+  pub profile: ProfileConfigItem,
+}
+
 pub struct UserSelection {
   session_id: SessionId,
   session_to_encode_dir: SessionToEncodeDir,
-  profile: ProfileConfigItem
+  profile: ProfileConfigItem,
+  /// The files of the session to encode, which may be fewer than all of them, and may override the profile
+  files: Vec<SelectedFile>,
 }
 
 impl UserSelection {
-  pub fn new(session_id: SessionId, session_to_encode_dir: SessionToEncodeDir, profile: ProfileConfigItem) -> Self {
+  pub fn new(session_id: SessionId, session_to_encode_dir: SessionToEncodeDir, profile: ProfileConfigItem, files: Vec<SelectedFile>) -> Self {
     Self {
       session_id,
       session_to_encode_dir,
-      profile
+      profile,
+      files,
     }
   }
 
-  pub fn session_to_encode_dir(&self) -> SessionToEncodeDir {
-    self.session_to_encode_dir.clone()
+  /// Select every file of the session, with the profile of the session.
+  pub fn all_files(session_id: SessionId, session_to_encode_dir: SessionToEncodeDir, profile: ProfileConfigItem) -> Self {
+    let files =
+      session_to_encode_dir
+        .rename_files()
+        .into_iter()
+        .map(|input| SelectedFile { input, profile: profile.clone() })
+        .collect();
+
+    Self::new(session_id, session_to_encode_dir, profile, files)
   }
 
+  pub fn session_id(&self) -> &SessionId {
+    &self.session_id
+  }
 
-  pub fn encode_dir_path(&self) -> PathBuf {
-    self.session_to_encode_dir.encode_dir_path()
+  pub fn files(&self) -> &[SelectedFile] {
+    &self.files
   }
 
-  pub fn profile(&self) -> &ProfileConfigItem {
-    &self.profile
+
+  pub fn encode_dir_path(&self) -> PathBuf {
+    self.session_to_encode_dir.encode_dir_path()
   }
 }
 
 impl fmt::Display for UserSelection {
   fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-    let UserSelection { session_id, session_to_encode_dir, profile } = self;
+    let UserSelection { session_id, session_to_encode_dir, profile, files } = self;
     let location = &session_to_encode_dir.location();
-    write!(f, "Copy {} -> {} with {}", style(session_id).yellow(), style(location).underlined(), style(profile).blue())
+    write!(f, "Copy {} -> {} with {}", style(session_id).yellow(), style(location).underlined(), style(profile).blue())?;
+
+    let file_count = session_to_encode_dir.rename_files().len();
+    if files.len() < file_count {
+      write!(f, " ({} of {} files)", files.len(), file_count)?;
+    }
+
+    for file in files.iter().filter(|file| file.profile.to_string() != profile.to_string()) {
+      write!(f, "\n    {} with {}", file.input.mkv_file, style(&file.profile).blue())?;
+    }
+
+    Ok(())
   }
 }
 
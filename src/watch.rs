@@ -0,0 +1,144 @@
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::time::{Duration, Instant, SystemTime};
+
+use notify::{Event, RecommendedWatcher, RecursiveMode, Watcher};
+
+use crate::handbrake::Interrupt;
+use crate::models::{InputFile, SessionToEncodeDir};
+
+/// How often a waiting watch checks whether it has been cancelled
+const INTERRUPT_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How long the renames must go without changing before they are scanned, so that files still being written by
+/// mkv-renamer are left for the next scan
+const SETTLE_TIME: Duration = Duration::from_secs(5);
+
+/// Waits for the sessions under a source to change.
+pub enum SessionChanges {
+  /// Woken by the filesystem when an encode_dir.txt or a rename changes, and after every interval to pick up renames
+  /// that were still changing at the last scan, or changes the filesystem missed
+  Notify {
+    _watcher: RecommendedWatcher,
+    events: Receiver<notify::Result<Event>>,
+    interval: Duration,
+  },
+  /// Woken after every interval, for filesystems that can't notify deo, such as some network shares
+  Poll {
+    interval: Duration,
+  },
+}
+
+impl SessionChanges {
+  /// Watch the Rips directory of the source, or the source itself when there are no rips yet.
+  pub fn watch(source: &Path, interval: Duration) -> Result<Self, String> {
+    let rips_dir = source.join("Rips");
+    let watched_dir = if rips_dir.is_dir() { rips_dir } else { source.to_owned() };
+
+    let (sender, events) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(sender).map_err(|e| e.to_string())?;
+
+    watcher
+      .watch(&watched_dir, RecursiveMode::Recursive)
+      .map_err(|e| e.to_string())?;
+
+    Ok(SessionChanges::Notify { _watcher: watcher, events, interval })
+  }
+
+  pub fn poll(interval: Duration) -> Self {
+    SessionChanges::Poll { interval }
+  }
+
+  /// Wait until the sessions should be scanned again. Returns false when the watch has been cancelled.
+  pub fn wait(&self, interrupt: &Interrupt) -> bool {
+    match self {
+      SessionChanges::Notify { events, interval, .. } => wait_for_events(events, *interval, interrupt),
+      SessionChanges::Poll { interval } => wait_for_interval(*interval, interrupt),
+    }
+  }
+}
+
+fn wait_for_events(events: &Receiver<notify::Result<Event>>, interval: Duration, interrupt: &Interrupt) -> bool {
+  let start = Instant::now();
+
+  loop {
+    if interrupt.is_requested() {
+      return false
+    }
+
+    if start.elapsed() >= interval {
+      return true
+    }
+
+    match events.recv_timeout(INTERRUPT_CHECK_INTERVAL.min(interval.saturating_sub(start.elapsed()))) {
+      Ok(Ok(event)) if event.paths.iter().any(|path| is_session_change(path)) => break,
+      Ok(_) | Err(RecvTimeoutError::Timeout) => (),
+      Err(RecvTimeoutError::Disconnected) => return false,
+    }
+  }
+
+  // Wait for the session to stop changing before scanning it
+  while events.recv_timeout(SETTLE_TIME).is_ok() {}
+
+  !interrupt.is_requested()
+}
+
+fn wait_for_interval(interval: Duration, interrupt: &Interrupt) -> bool {
+  let start = Instant::now();
+
+  while start.elapsed() < interval {
+    if interrupt.is_requested() {
+      return false
+    }
+
+    std::thread::sleep(INTERRUPT_CHECK_INTERVAL.min(interval.saturating_sub(start.elapsed())))
+  }
+
+  !interrupt.is_requested()
+}
+
+/// Changes to the encode_dir.txt of a session, or to any of its renames.
+fn is_session_change(path: &Path) -> bool {
+  let is_encode_dir_file = path.file_name().is_some_and(|file_name| file_name == "encode_dir.txt");
+  let is_rename = path.extension().is_some_and(|extension| extension == "mkv") && path.parent().is_some_and(|parent| parent.ends_with("renames"));
+
+  is_encode_dir_file || is_rename
+}
+
+/// The renames of a session that haven't been queued by this watch or encoded by an earlier run, and that have
+/// stopped changing.
+pub fn unqueued_files(session_to_encode_dir: &SessionToEncodeDir, queued: &BTreeSet<PathBuf>, encoded: &BTreeSet<PathBuf>) -> Vec<InputFile> {
+  session_to_encode_dir
+    .rename_files()
+    .into_iter()
+    .filter(|input| !queued.contains(&input.mkv_path) && !encoded.contains(&input.mkv_path))
+    .filter(|input| is_settled(&input.mkv_path))
+    .collect()
+}
+
+fn is_settled(path: &Path) -> bool {
+  std::fs::metadata(path)
+    .and_then(|metadata| metadata.modified())
+    .ok()
+    .and_then(|modified| SystemTime::now().duration_since(modified).ok())
+    .is_none_or(|age| age >= SETTLE_TIME)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rescans_when_a_session_changes() {
+      assert!(is_session_change(Path::new("/media/Rips/session1/renames/encode_dir.txt")));
+      assert!(is_session_change(Path::new("/media/Rips/session1/renames/S01E01 - Exodus.mkv")));
+    }
+
+    #[test]
+    fn ignores_changes_outside_of_sessions() {
+      assert!(!is_session_change(Path::new("/media/Rips/session1/disc1/title_t00.mkv")));
+      assert!(!is_session_change(Path::new("/media/Rips/session1/renames/notes.txt")));
+      assert!(!is_session_change(Path::new("/media/Rips/session1")));
+    }
+}
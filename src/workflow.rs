@@ -1,33 +1,406 @@
-use crate::args::cli::Args;
-use crate::{file_mapper, handbrake, user_choices};
-use crate::profiles::{self, ProfileConfig};
+use crate::args::cli::{Args, DeoCommand};
+use crate::{file_mapper, handbrake, rules, user_choices, watch};
+use crate::config::{self, DeoConfig};
+use crate::handbrake::{DisposeAfter, EncodeJob, EncodeOptions, HandbrakeInfo, Report, ReportFormat, ScanFormat, SourceAction, SourceDisposition};
+use crate::journal::{self, Journal};
+use crate::profiles::{self, ProfileConfig, ProfileConfigItem};
+use crate::user_selection::{SelectedFile, UserSelection};
+use crate::watch::SessionChanges;
 use crate::colours;
+use crate::error::{DeoConfigError, DeoJournalError, DeoRulesError, DeoSelectionError};
+use crate::rules::ProfileRules;
 use console::style;
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 
 
 pub fn perform(args: Args) {
-  match profiles::read_profile_config() {
-    Ok(profile_config) => encode_profiles(args, profile_config),
+  match config::read_config(args.config.as_deref()) {
+    Ok(config) => perform_command(args, config),
     Err(error) => eprintln!("{}", style(error).bg(colours::RED))
   }
 }
 
 
-fn encode_profiles(args: Args, profile_config: ProfileConfig) {
-  let sessions_to_encode_dir = file_mapper::get_session_encode_mapping(args.source, args.verbose);
+fn perform_command(args: Args, config: DeoConfig) {
+  match args.command {
+    Some(DeoCommand::Resume) => resume_encode(args, config),
+    Some(DeoCommand::Scan { format }) => scan_sources(&args, &config, format),
+    Some(DeoCommand::Watch { interval }) => {
+      match profiles::read_profile_config(&config.profiles_dir()) {
+        Ok(profile_config) => watch_sources(&args, &config, &profile_config, Duration::from_secs(interval)),
+        Err(error) => eprintln!("{}", style(error).bg(colours::RED))
+      }
+    },
+    None => {
+      match profiles::read_profile_config(&config.profiles_dir()) {
+        Ok(profile_config) => encode_profiles(args, config, profile_config),
+        Err(error) => eprintln!("{}", style(error).bg(colours::RED))
+      }
+    }
+  }
+}
+
+
+fn encode_profiles(args: Args, config: DeoConfig, profile_config: ProfileConfig) {
+  let Some(source) = args.source.clone().or_else(|| config.source()) else {
+    return eprintln!("{}", style("No source directory supplied. Use --source or set source in the config file").bg(colours::RED))
+  };
+
+  let sessions_to_encode_dir = file_mapper::get_session_encode_mapping(source, args.verbose);
   if sessions_to_encode_dir.is_empty() {
     println!("Could not find any renames to encode")
   } else {
-    match user_choices::interact_with_user(sessions_to_encode_dir, profile_config) {
+    let batch =
+      user_choices::BatchChoices {
+        profile: args.profile.clone(),
+        sessions: args.sessions.clone(),
+        skip_confirmation: args.yes,
+      };
+
+    let extension = output_extension(&args, &config);
+
+    let profile_rules = match read_rules(&config, &profile_config) {
+      Ok(profile_rules) => profile_rules,
+      Err(error) => return eprintln!("{}", style(error).bg(colours::RED)),
+    };
+
+    match user_choices::interact_with_user(sessions_to_encode_dir, profile_config.clone(), &profile_rules, batch, args.verbose) {
+      user_choices::Interaction::ProceedToEncode(selections) if args.dry_run => {
+        warn_about_containers(&selections, extension.as_deref(), &config, &profile_config);
+        handbrake::dry_run(selections, &config.encoders(), extension.as_deref(), args.scratch_dir.clone().or_else(|| config.scratch_dir()).as_deref())
+      },
       user_choices::Interaction::ProceedToEncode(selections) => {
-        match handbrake::encoder_with_handbrake(selections) {
-          Ok(_) => (),
+        warn_about_containers(&selections, extension.as_deref(), &config, &profile_config);
+        let jobs = match with_fallback_profiles(selections_to_jobs(selections, extension.as_deref()), &config, &profile_config) {
+          Ok(jobs) => jobs,
+          Err(error) => return eprintln!("{}", style(error).bg(colours::RED)),
+        };
+
+        if !replace_unfinished_journal(args.yes) {
+          return
+        }
+
+        match Journal::new(jobs) {
+          Ok(journal) => encode_journal(journal, &args, &config),
           Err(error) => eprintln!("{}", style(error).bg(colours::RED)),
         }
       },
       user_choices::Interaction::NoFilesToEncode => eprintln!("{}", style("No files to encode").bg(colours::RED)),
       user_choices::Interaction::CancelEncode => println!("User cancelled encoding"),
+      user_choices::Interaction::InvalidSelection(error) => eprintln!("{}", style(error).bg(colours::RED)),
       user_choices::Interaction::InteractionError(error) => eprintln!("{}", style(format!("Interaction with the user raised an error: {}", error)).bg(colours::RED)),
     }
   }
 }
+
+
+/// A new run replaces the journal, so check with the user before the unfinished files of the last run are forgotten.
+fn replace_unfinished_journal(skip_confirmation: bool) -> bool {
+  match journal::check_unfinished() {
+    Ok(()) => true,
+    Err(DeoJournalError::UnfinishedJournal(journal, count)) if skip_confirmation => {
+      println!("{}", style(format!("Replacing the journal at: {} and its {} unfinished files", journal, count)).yellow());
+      true
+    },
+    Err(DeoJournalError::UnfinishedJournal(journal, count)) => {
+      let prompt = format!("The journal at: {} has {} unfinished files that `deo resume` would encode, replace it?", journal, count);
+      match user_choices::confirm(&prompt) {
+        Ok(true) => true,
+        Ok(false) => {
+          println!("User cancelled encoding");
+          false
+        },
+        Err(error) => {
+          eprintln!("{}", style(format!("Interaction with the user raised an error: {}", error)).bg(colours::RED));
+          false
+        },
+      }
+    },
+    Err(error) => {
+      eprintln!("{}", style(error).bg(colours::RED));
+      false
+    },
+  }
+}
+
+
+fn scan_sources(args: &Args, config: &DeoConfig, format: ScanFormat) {
+  let Some(source) = args.source.clone().or_else(|| config.source()) else {
+    return eprintln!("{}", style("No source directory supplied. Use --source or set source in the config file").bg(colours::RED))
+  };
+
+  let sessions_to_encode_dir = file_mapper::get_session_encode_mapping(source, args.verbose);
+  if sessions_to_encode_dir.is_empty() {
+    return println!("Could not find any renames to scan")
+  }
+
+  match user_choices::filter_sessions(sessions_to_encode_dir, &args.sessions) {
+    Ok(sessions) => handbrake::scan_sources(&config.handbrake_cli(), sessions, format),
+    Err(error) => eprintln!("{}", style(error).bg(colours::RED)),
+  }
+}
+
+
+fn watch_sources(args: &Args, config: &DeoConfig, profile_config: &ProfileConfig, interval: Duration) {
+  let Some(source) = args.source.clone().or_else(|| config.source()) else {
+    return eprintln!("{}", style("No source directory supplied. Use --source or set source in the config file").bg(colours::RED))
+  };
+
+  let profile_rules = match read_rules(config, profile_config) {
+    Ok(profile_rules) => profile_rules,
+    Err(error) => return eprintln!("{}", style(error).bg(colours::RED)),
+  };
+
+  let default_profile =
+    match args.profile.clone().or_else(|| config.default_profile()) {
+      Some(profile_name) => match profile_config.find(&profile_name) {
+        Some(profile) => Some(profile),
+        None => return eprintln!("{}", style(DeoSelectionError::UnknownProfile(profile_name, profile_config.names())).bg(colours::RED)),
+      },
+      None => None,
+    };
+
+  if default_profile.is_none() && profile_rules.is_empty() {
+    return eprintln!("{}", style("No profile to queue new sessions with. Use --profile, set default_profile in the config file or add rules").bg(colours::RED))
+  }
+
+  // Every session the watch queues is appended to this journal, so a watch that's stopped can be resumed
+  if !args.dry_run {
+    if let Err(error) = journal::start_watch_journal() {
+      return eprintln!("{}", style(error).bg(colours::RED))
+    }
+  }
+
+  let interrupt = match handbrake::install_interrupt_handler() {
+    Ok(interrupt) => interrupt,
+    Err(error) => return eprintln!("{}", style(error).bg(colours::RED)),
+  };
+
+  let changes =
+    match SessionChanges::watch(&source, interval) {
+      Ok(changes) => {
+        println!("watching {} for new sessions, press Ctrl-C to stop", source.to_string_lossy());
+        changes
+      },
+      Err(error) => {
+        println!("{}", style(format!("Could not watch {} for changes: {}, scanning every {} seconds instead", source.to_string_lossy(), error, interval.as_secs())).yellow());
+        SessionChanges::poll(interval)
+      },
+    };
+
+  // Every file queued since the watch started, whether it was encoded or not
+  let mut queued = BTreeSet::new();
+
+  loop {
+    queue_new_sessions(args, config, profile_config, &source, &profile_rules, default_profile, &mut queued);
+
+    if interrupt.is_requested() || !changes.wait(&interrupt) {
+      break
+    }
+  }
+
+  println!("stopped watching {}", source.to_string_lossy())
+}
+
+
+/// Encode the files of every session that haven't been queued or encoded before, with the profile of the rule that
+/// matches the session or else the default profile.
+fn queue_new_sessions(args: &Args, config: &DeoConfig, profile_config: &ProfileConfig, source: &Path, profile_rules: &ProfileRules, default_profile: Option<&ProfileConfigItem>, queued: &mut BTreeSet<PathBuf>) {
+  let encoded = handbrake::encoded_inputs(&config.log_dir());
+  let mut selections: Vec<UserSelection> = vec![];
+
+  for sed in file_mapper::get_session_encode_mapping(source, args.verbose) {
+    let files = watch::unqueued_files(&sed, queued, &encoded);
+    if files.is_empty() {
+      continue
+    }
+
+    let rule_profile = rules::rule_for_session(profile_rules, &sed, args.verbose).map(|rule| rule.profile());
+    let Some(profile) = rule_profile.or(default_profile) else {
+      println!("{}", style(format!("No rule matches {} ({}) and there is no default profile, so it won't be encoded", sed.session_id().id(), sed.location())).yellow());
+      queued.extend(files.into_iter().map(|input| input.mkv_path));
+      continue
+    };
+
+    let files = files.into_iter().map(|input| SelectedFile { input, profile: profile.clone() }).collect();
+    selections.push(UserSelection::new(sed.session_id(), sed, profile.clone(), files));
+  }
+
+  if selections.is_empty() {
+    return
+  }
+
+  println!("Queueing:");
+  for selection in &selections {
+    println!("  {}", selection);
+    queued.extend(selection.files().iter().map(|file| file.input.mkv_path.clone()));
+  }
+
+  let extension = output_extension(args, config);
+  warn_about_containers(&selections, extension.as_deref(), config, profile_config);
+
+  if args.dry_run {
+    return handbrake::dry_run(selections, &config.encoders(), extension.as_deref(), args.scratch_dir.clone().or_else(|| config.scratch_dir()).as_deref())
+  }
+
+  let jobs = match with_fallback_profiles(selections_to_jobs(selections, extension.as_deref()), config, profile_config) {
+    Ok(jobs) => jobs,
+    Err(error) => return eprintln!("{}", style(error).bg(colours::RED)),
+  };
+
+  match journal::read_journal() {
+    Ok(mut journal) => {
+      journal.append(jobs);
+      encode_journal(journal, args, config)
+    },
+    Err(error) => eprintln!("{}", style(error).bg(colours::RED)),
+  }
+}
+
+
+/// Read the rules file from the config file, or the default rules file if there is one.
+fn read_rules(config: &DeoConfig, profile_config: &ProfileConfig) -> Result<ProfileRules, DeoRulesError> {
+  let (rules_file, required) = config.rules_file();
+  rules::read_rules(&rules_file, required, profile_config)
+}
+
+
+fn resume_encode(args: Args, config: DeoConfig) {
+  match journal::read_journal() {
+    Ok(journal) => {
+      let unfinished = journal.unfinished();
+      if unfinished.is_empty() {
+        println!("Nothing to resume, all files in the last run have finished")
+      } else {
+        println!("Resuming {} unfinished files:", unfinished.len());
+        for index in unfinished {
+          let entry = &journal.entries()[index];
+          println!("  {} ({}) with {}", entry.job.input.mkv_file, style(&entry.job.session_id).yellow(), style(&entry.job.profile).blue());
+        }
+
+        if args.dry_run {
+          println!("dry run, nothing will be encoded")
+        } else {
+          encode_journal(journal, &args, &config)
+        }
+      }
+    },
+    Err(error) => eprintln!("{}", style(error).bg(colours::RED)),
+  }
+}
+
+
+fn encode_journal(journal: Journal, args: &Args, config: &DeoConfig) {
+  let source_disposition =
+    SourceDisposition::new(
+      args.dispose_source.or(config.dispose_source()).unwrap_or(SourceAction::Keep),
+      args.dispose_after.or(config.dispose_after()).unwrap_or(DisposeAfter::File),
+      args.archive_dir.clone().or_else(|| config.archive_dir()),
+      args.failed_dir.clone().or_else(|| config.failed_dir()),
+    );
+
+  let Some(source_disposition) = source_disposition else {
+    return eprintln!("{}", style(DeoConfigError::ArchiveDirRequired).bg(colours::RED))
+  };
+
+  let options =
+    EncodeOptions {
+      encoders: config.encoders(),
+      on_existing: args.on_existing,
+      jobs: args.jobs.or(config.jobs()).unwrap_or(1),
+      keep_json_output: args.keep_json_output,
+      verify: args.verify().or(config.verify()).unwrap_or(false),
+      verify_tolerance: args.verify_tolerance.or(config.verify_tolerance()).unwrap_or(2),
+      stall_timeout: args.stall_timeout.or(config.stall_timeout()).map(minutes),
+      max_file_time: args.max_file_time.or(config.max_file_time()).map(minutes),
+      retries: args.retries.or(config.retries()).unwrap_or(0),
+      source_disposition,
+      hooks: config.hooks(),
+      log_dir: config.log_dir(),
+      scratch_dir: args.scratch_dir.clone().or_else(|| config.scratch_dir()),
+      check_space: !args.no_space_check,
+      min_free_space: handbrake::gib(args.min_free_space.or(config.min_free_space()).unwrap_or(1)),
+      output_size_factor: config.output_size_factor(),
+      report: args.report.clone().map(|path| Report { format: args.report_format.unwrap_or_else(|| ReportFormat::for_path(&path)), path }),
+    };
+
+  match handbrake::encode(journal, options) {
+    Ok(_) => (),
+    Err(error) => eprintln!("{}", style(error).bg(colours::RED)),
+  }
+}
+
+
+/// The extension from the command line or config file, without a leading dot.
+fn output_extension(args: &Args, config: &DeoConfig) -> Option<String> {
+  args
+    .extension
+    .clone()
+    .or_else(|| config.extension())
+    .map(|extension| extension.trim_start_matches('.').to_owned())
+}
+
+
+fn minutes(minutes: u64) -> Duration {
+  Duration::from_secs(minutes * 60)
+}
+
+
+fn selections_to_jobs(selections: Vec<UserSelection>, extension: Option<&str>) -> Vec<EncodeJob> {
+  selections
+    .into_iter()
+    .map(|selection| HandbrakeInfo::new(selection, extension))
+    .flat_map(|handbrake_info| handbrake_info.jobs())
+    .collect()
+}
+
+
+/// Warn once for each selected profile, or fallback profile of one, whose container doesn't match the extension the
+/// outputs will be given.
+fn warn_about_containers(selections: &[UserSelection], extension: Option<&str>, config: &DeoConfig, profile_config: &ProfileConfig) {
+  let Some(extension) = extension else {
+    return
+  };
+
+  let mismatched_profiles: BTreeSet<String> =
+    selections
+      .iter()
+      .flat_map(|selection| selection.files())
+      .flat_map(|file| {
+        let fallback_profile = config.fallback_profile(&file.profile.to_string()).and_then(|fallback| profile_config.find(fallback));
+        std::iter::once(&file.profile).chain(fallback_profile)
+      })
+      .filter(|profile| !profile.container().suits_extension(extension))
+      .map(|profile| format!("{} ({})", profile, profile.container().extension()))
+      .collect();
+
+  for profile in mismatched_profiles {
+    println!("{}", style(format!("Warning: profile {} encodes to a different container than the requested extension: {}", profile, extension)).yellow())
+  }
+}
+
+
+/// Add the fallback profile from the config file to each job whose profile has one. The output of a job is named
+/// for the container of its profile, so the fallback profile must encode to the same container.
+fn with_fallback_profiles(jobs: Vec<EncodeJob>, config: &DeoConfig, profile_config: &ProfileConfig) -> Result<Vec<EncodeJob>, DeoSelectionError> {
+  jobs
+    .into_iter()
+    .map(|job| {
+      let profile = job.profile.to_string();
+      match config.fallback_profile(&profile) {
+        None => Ok(job),
+        Some(fallback) => {
+          match profile_config.find(fallback) {
+            None => Err(DeoSelectionError::MissingFallbackProfile(profile, fallback.to_owned(), profile_config.names())),
+            Some(fallback_profile) if fallback_profile.container() != job.profile.container() => {
+              Err(DeoSelectionError::FallbackContainerMismatch(profile, job.profile.container().extension().to_owned(), fallback.to_owned(), fallback_profile.container().extension().to_owned()))
+            },
+            Some(fallback_profile) => Ok(EncodeJob { fallback_profile: Some(fallback_profile.clone()), ..job }),
+          }
+        },
+      }
+    })
+    .collect()
+}